@@ -1,4 +1,6 @@
-use std::fs;
+extern crate day;
+
+use std::num::ParseIntError;
 
 fn fuel(mass: i64) -> i64 {
     mass / 3 - 2
@@ -12,15 +14,74 @@ fn extra_fuel(mass: i64) -> i64 {
     }
 }
 
+fn parse_masses(input: &str) -> Result<Vec<i64>, ParseIntError> {
+    input.lines().map(|line| line.trim().parse::<i64>()).collect()
+}
+
+fn total_fuel_both(masses: &[i64]) -> (i64, i64) {
+    masses.iter().fold((0, 0), |(sum, extra_sum), mass| {
+        (sum + fuel(*mass), extra_sum + extra_fuel(*mass))
+    })
+}
+
+struct Day1;
+
+impl Day1 {
+    fn totals(&self, input: &str) -> Result<(i64, i64), ParseIntError> {
+        Ok(total_fuel_both(&parse_masses(input)?))
+    }
+}
+
+impl day::Day for Day1 {
+    type Error = ParseIntError;
+
+    fn part1(&self, input: &str) -> Result<String, ParseIntError> {
+        Ok(self.totals(input)?.0.to_string())
+    }
+
+    fn part2(&self, input: &str) -> Result<String, ParseIntError> {
+        Ok(self.totals(input)?.1.to_string())
+    }
+
+    /// Both totals come out of the same pass over the masses
+    fn solve(&self, input: &str) -> Result<(String, String), ParseIntError> {
+        let (sum, extra_sum) = self.totals(input)?;
+        Ok((sum.to_string(), extra_sum.to_string()))
+    }
+}
+
 fn main() {
-    let input = fs::read_to_string("input").unwrap();
+    day::run(1, &Day1, "input");
+}
 
-    let sum: i64 = input.lines().map(|line| {
-        fuel(line.parse::<i64>().unwrap())
-    }).sum();
-    let extra_sum: i64 = input.lines().map(|line| {
-        extra_fuel(line.parse::<i64>().unwrap())
-    }).sum();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use day::Day;
 
-    println!("1-1:\n{}\n1-2:\n{}", sum, extra_sum);
+    #[test]
+    fn example_masses() {
+        let masses = parse_masses("12\n14\n1969\n100756\n").unwrap();
+        assert_eq!(total_fuel_both(&masses), (2 + 2 + 654 + 33583, 2 + 2 + 966 + 50346));
+        assert_eq!(total_fuel_both(&[]), (0, 0));
+        assert!(parse_masses("12\nfourteen\n").is_err());
+    }
+
+    #[test]
+    fn parts_against_fixtures() {
+        let masses = "12\n14\n1969\n100756\n";
+        assert_eq!(Day1.part1(masses).unwrap(), "34241");
+        assert_eq!(Day1.part2(masses).unwrap(), "51316");
+        let input = std::fs::read_to_string("input").unwrap();
+        let answers = Day1.solve(&input).unwrap();
+        assert_eq!(answers, ("3553700".to_string(), "5327664".to_string()));
+        assert_eq!(answers, (Day1.part1(&input).unwrap(), Day1.part2(&input).unwrap()));
+    }
+
+    #[test]
+    fn invalid_mass_is_an_error() {
+        assert!(Day1.part1("12\nfourteen\n").is_err());
+        assert!(Day1.part2("12\nfourteen\n").is_err());
+        assert!(Day1.solve("12\nfourteen\n").is_err());
+    }
 }
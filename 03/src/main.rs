@@ -1,4 +1,5 @@
 use std::cmp;
+use std::collections::HashMap;
 use std::fs;
 
 #[derive(Debug)]
@@ -77,7 +78,8 @@ fn find_intersections(a: &Layout, b: &Layout) -> IntersectionList {
     let b_vertical_wires: Layout = b.iter().filter(|p| p.0.x == p.1.x).cloned().collect();
     let b_horizontal_wires: Layout = b.iter().filter(|p| p.0.y == p.1.y).cloned().collect();
 
-    let mut intersections: IntersectionList = vec![];
+    // A wire can pass the same crossing more than once, so only keep the fewest combined steps per point
+    let mut intersections: HashMap<(i64, i64), i64> = HashMap::new();
 
     let mut search = |vertical_wires: Layout, horizontal_wires: Layout| {
         for v_wire in &vertical_wires {
@@ -89,13 +91,12 @@ fn find_intersections(a: &Layout, b: &Layout) -> IntersectionList {
                 {
                     let v_wire_extra_distance = (v_wire.0.y - h_wire.0.y).abs();
                     let h_wire_extra_distance = (h_wire.0.x - v_wire.0.x).abs();
-                    intersections.push((
-                        Point {
-                            x: v_wire.0.x,
-                            y: h_wire.0.y,
-                        },
-                        v_wire.2 + h_wire.2 + v_wire_extra_distance + h_wire_extra_distance,
-                    ));
+                    let distance =
+                        v_wire.2 + h_wire.2 + v_wire_extra_distance + h_wire_extra_distance;
+                    intersections
+                        .entry((v_wire.0.x, h_wire.0.y))
+                        .and_modify(|d| *d = cmp::min(*d, distance))
+                        .or_insert(distance);
                 }
             }
         }
@@ -104,6 +105,65 @@ fn find_intersections(a: &Layout, b: &Layout) -> IntersectionList {
     search(b_vertical_wires, a_horizontal_wires);
 
     intersections
+        .into_iter()
+        .map(|((x, y), distance)| (Point { x, y }, distance))
+        .collect()
+}
+
+/// Every cell a wire passes over, drawn as `-` or `|` depending on the direction of the segment it's part of
+#[allow(dead_code)]
+fn occupied_cells(layout: &Layout) -> HashMap<(i64, i64), char> {
+    let mut cells = HashMap::new();
+    for (from, to, _) in layout {
+        let c = if from.y == to.y { '-' } else { '|' };
+        for x in cmp::min(from.x, to.x)..=cmp::max(from.x, to.x) {
+            for y in cmp::min(from.y, to.y)..=cmp::max(from.y, to.y) {
+                cells.insert((x, y), c);
+            }
+        }
+    }
+    cells
+}
+
+/// Smallest box holding both wires and the origin, as its bottom left and top right corners
+#[allow(dead_code)]
+fn bounding_box(a: &Layout, b: &Layout) -> (Point, Point) {
+    let origin = Point { x: 0, y: 0 };
+    a.iter()
+        .chain(b.iter())
+        .flat_map(|(from, to, _)| vec![*from, *to])
+        .fold((origin, origin), |(min, max), p| {
+            (
+                Point {
+                    x: cmp::min(min.x, p.x),
+                    y: cmp::min(min.y, p.y),
+                },
+                Point {
+                    x: cmp::max(max.x, p.x),
+                    y: cmp::max(max.y, p.y),
+                },
+            )
+        })
+}
+
+/// Draws both wires with up at the top, marking the origin with `o` and cells both wires cross with `X`
+#[allow(dead_code)]
+fn render(a: &Layout, b: &Layout) -> String {
+    let (a_cells, b_cells) = (occupied_cells(a), occupied_cells(b));
+    let (min, max) = bounding_box(a, b);
+    let mut output = String::new();
+    for y in (min.y..=max.y).rev() {
+        for x in min.x..=max.x {
+            output.push(match (a_cells.get(&(x, y)), b_cells.get(&(x, y))) {
+                _ if (x, y) == (0, 0) => 'o',
+                (Some(_), Some(_)) => 'X',
+                (Some(c), None) | (None, Some(c)) => *c,
+                (None, None) => '.',
+            });
+        }
+        output.push('\n');
+    }
+    output
 }
 
 fn main() {
@@ -124,3 +184,43 @@ fn main() {
     total_distances.sort();
     println!("3-2:\n{:?}", total_distances[0]);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn revisited_crossing_keeps_fewest_steps() {
+        // The first wire goes up through (3, 1) and later comes back down through it
+        let a = parse_layout("R3,U4,L1,D1,R1,D5");
+        let b = parse_layout("U1,R6");
+        let intersections = find_intersections(&a, &b);
+        assert_eq!(intersections.len(), 1);
+        let (point, distance) = intersections[0];
+        assert_eq!((point.x, point.y, distance), (3, 1, 8));
+    }
+
+    #[test]
+    fn render_small_example() {
+        let a = parse_layout("R8,U5,L5,D3");
+        let b = parse_layout("U7,R6,D4,L4");
+        let (min, max) = bounding_box(&a, &b);
+        assert_eq!((min.x, min.y, max.x, max.y), (0, 0, 8, 7));
+        let cells = occupied_cells(&a);
+        assert_eq!(cells.get(&(4, 0)), Some(&'-'));
+        assert_eq!(cells.get(&(8, 2)), Some(&'|'));
+        assert_eq!(cells.get(&(4, 4)), None);
+        let rows = render(&a, &b)
+            .lines()
+            .map(|row| row.chars().collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        assert_eq!(rows.len(), 8);
+        // Rows run from the top down, so y is counted from the bottom row
+        let at = |x: usize, y: usize| rows[7 - y][x];
+        assert_eq!(at(0, 0), 'o');
+        assert_eq!(at(3, 3), 'X');
+        assert_eq!(at(6, 5), 'X');
+        assert_eq!(at(4, 7), '-');
+        assert_eq!(at(7, 7), '.');
+    }
+}
@@ -19,58 +19,143 @@ fn parse_range(filename: &str) -> Range {
     }
 }
 
-fn is_valid_password(password: i64) -> bool {
-    let password = password.to_string();
-    let mut previous: Option<char> = None;
-    let mut has_successive_char = false;
-    for c in password.chars() {
+/// Digits of `n` in the given base, most significant first
+fn digits(n: i64, base: u32) -> Vec<u32> {
+    assert!(base >= 2, "Base {} has no digits to work with", base);
+    assert!(n >= 0, "Can't take the digits of negative number {}", n);
+    let mut n = n;
+    let mut digits = vec![];
+    loop {
+        digits.push((n % base as i64) as u32);
+        n /= base as i64;
+        if n == 0 {
+            break;
+        }
+    }
+    digits.reverse();
+    digits
+}
+
+fn is_valid_password(password: &[u32]) -> bool {
+    let mut previous: Option<u32> = None;
+    let mut has_successive_digit = false;
+    for d in password {
         if let Some(p) = previous {
-            if c == p {
-                has_successive_char = true;
+            if *d == p {
+                has_successive_digit = true;
             }
-            if c < p {
+            if *d < p {
                 return false;
             }
         }
-        previous = Some(c);
+        previous = Some(*d);
     }
 
-    has_successive_char
+    has_successive_digit
 }
 
-fn is_really_valid_password(password: i64) -> bool {
-    if !is_valid_password(password) {
-        return false;
-    }
-
-    let password = password.to_string();
-    let mut previous: Option<char> = None;
-    let mut successive_char_count = 0;
-    for c in password.chars() {
+/// Whether some digit repeats exactly twice in a row, rather than as part of a longer run
+fn has_exact_pair(password: &[u32]) -> bool {
+    let mut previous: Option<u32> = None;
+    let mut successive_digit_count = 0;
+    for d in password {
         if let Some(p) = previous {
-            if c == p {
-                successive_char_count += 1;
+            if *d == p {
+                successive_digit_count += 1;
             } else {
-                if successive_char_count == 1 {
+                if successive_digit_count == 1 {
                     return true;
                 }
-                successive_char_count = 0;
+                successive_digit_count = 0;
             }
         }
-        previous = Some(c);
+        previous = Some(*d);
     }
 
-    successive_char_count == 1
+    successive_digit_count == 1
+}
+
+/// Counts (valid, really valid) passwords in a single pass, since being really valid implies being valid.
+fn classify(range: &Range, base: u32) -> (usize, usize) {
+    let (mut valid, mut really_valid) = (0, 0);
+    for password in range.min..range.max {
+        let password = digits(password, base);
+        if is_valid_password(&password) {
+            valid += 1;
+            if has_exact_pair(&password) {
+                really_valid += 1;
+            }
+        }
+    }
+    (valid, really_valid)
 }
 
 fn main() {
     let range = parse_range("input");
-    let valid_passwords = (range.min..range.max)
-        .filter(|p| is_valid_password(*p))
-        .collect::<Vec<_>>();
-    let really_valid_passwords = (range.min..range.max)
-        .filter(|p| is_really_valid_password(*p))
-        .collect::<Vec<_>>();
-    println!("4-1:\n{}", valid_passwords.len());
-    println!("4-2:\n{}", really_valid_passwords.len());
+    let (valid_passwords, really_valid_passwords) = classify(&range, 10);
+    println!("4-1:\n{}", valid_passwords);
+    println!("4-2:\n{}", really_valid_passwords);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classify_matches_separate_filters() {
+        let range = Range {
+            min: 111_000,
+            max: 124_000,
+        };
+        let count = |rule: fn(&[u32]) -> bool| {
+            (range.min..range.max)
+                .filter(|n| rule(&digits(*n, 10)))
+                .count()
+        };
+        let expected = (
+            count(is_valid_password),
+            count(|password| is_valid_password(password) && has_exact_pair(password)),
+        );
+        assert_eq!(classify(&range, 10), expected);
+        assert!(expected.0 > expected.1 && expected.1 > 0);
+    }
+
+    #[test]
+    fn digits_in_other_bases() {
+        assert_eq!(digits(0, 10), vec![0]);
+        assert_eq!(digits(7, 2), vec![1, 1, 1]);
+        assert_eq!(digits(123_456, 10), vec![1, 2, 3, 4, 5, 6]);
+        assert_eq!(digits(0x1a_fe, 16), vec![1, 10, 15, 14]);
+        assert_eq!(digits(255, 16), vec![15, 15]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Base 1")]
+    fn digits_rejects_tiny_base() {
+        digits(10, 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "negative")]
+    fn digits_rejects_negative_numbers() {
+        digits(-5, 10);
+    }
+
+    #[test]
+    fn base_16_passwords() {
+        // 0x11_ab_cf is 1, 1, a, b, c, f
+        assert!(is_valid_password(&digits(0x11_ab_cf, 16)));
+        assert!(has_exact_pair(&digits(0x11_ab_cf, 16)));
+        // Decreasing from f to e
+        assert!(!is_valid_password(&digits(0x11_ab_fe, 16)));
+        // Only pair is part of a run of three
+        let triple = digits(0x1a_bf_ff, 16);
+        assert!(is_valid_password(&triple));
+        assert!(!has_exact_pair(&triple));
+        let range = Range {
+            min: 0xff_ff_f0,
+            max: 0xff_ff_ff + 1,
+        };
+        assert_eq!(classify(&range, 16), (1, 0));
+    }
 }
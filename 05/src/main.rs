@@ -1,4 +1,6 @@
-use std::fs;
+extern crate day;
+
+use std::num::ParseIntError;
 
 #[derive(Debug)]
 enum OpCode {
@@ -127,11 +129,10 @@ impl Op {
     }
 }
 
-fn parse_rom(filename: &str) -> Vec<i64> {
-    let input = fs::read_to_string(filename).unwrap();
+fn parse_rom(input: &str) -> Result<Vec<i64>, ParseIntError> {
     input
         .split(",")
-        .map(|op| op.trim().parse().unwrap())
+        .map(|op| op.trim().parse())
         .collect()
 }
 
@@ -156,9 +157,11 @@ impl Cpu {
         self.sp = 0;
     }
 
-    fn run(&mut self, input: i64) -> Vec<i64> {
+    /// Runs the program, with each IN op reading the next value of `inputs` in turn
+    fn run(&mut self, inputs: &[i64]) -> Vec<i64> {
         self.clear_registers();
         let mut outputs: Vec<i64> = vec![];
+        let mut inputs = inputs.iter();
 
         loop {
             let op = Op::new(&self.memory, self.sp);
@@ -182,7 +185,7 @@ impl Cpu {
                 OpCode::IN => {
                     self.ax = op.params[0].read(&self.memory, self.sp);
 
-                    self.memory[self.ax as usize] = input;
+                    self.memory[self.ax as usize] = *inputs.next().expect("Missing input parameter");
                     self.sp += op.len();
                 }
                 OpCode::OUT => {
@@ -232,10 +235,88 @@ impl Cpu {
     }
 }
 
+/// The diagnostic program outputs 0 for every passing test and then its code, so this is `None` if any test failed
+fn diagnostic_code(outputs: &[i64]) -> Option<i64> {
+    let (code, tests) = outputs.split_last()?;
+    if tests.iter().all(|output| *output == 0) {
+        Some(*code)
+    } else {
+        None
+    }
+}
+
+/// Runs the diagnostic program for `system_id` and returns its code, panicking if any self-test failed
+fn run_diagnostic(input: &str, system_id: i64) -> Result<String, ParseIntError> {
+    let outputs = Cpu::new(parse_rom(input)?).run(&[system_id]);
+    match diagnostic_code(&outputs) {
+        Some(code) => Ok(code.to_string()),
+        None => panic!("Diagnostic self-test failed: {:?}", outputs),
+    }
+}
+
+struct Day5;
+
+impl day::Day for Day5 {
+    type Error = ParseIntError;
+
+    fn part1(&self, input: &str) -> Result<String, ParseIntError> {
+        run_diagnostic(input, 1)
+    }
+
+    fn part2(&self, input: &str) -> Result<String, ParseIntError> {
+        run_diagnostic(input, 5)
+    }
+}
+
 fn main() {
-    let rom = parse_rom("input");
-    println!("5-1:");
-    println!("{:?}", Cpu::new(rom.to_vec()).run(1));
-    println!("5-2:");
-    println!("{:?}", Cpu::new(rom.to_vec()).run(5));
+    day::run(5, &Day5, "input");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use day::Day;
+
+    #[test]
+    fn parts_against_fixtures() {
+        let input = std::fs::read_to_string("input").unwrap();
+        assert_eq!(Day5.part1(&input).unwrap(), "7988899");
+        assert_eq!(Day5.part2(&input).unwrap(), "13758663");
+        // Echoes its input
+        assert_eq!(Day5.part1("3,0,4,0,99").unwrap(), "1");
+        assert_eq!(Day5.part2("3,0,4,0,99").unwrap(), "5");
+        assert!(Day5.part1("3,0,four,0,99").is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "Diagnostic self-test failed")]
+    fn part1_fails_on_a_failed_self_test() {
+        // Outputs 1 then 0, so the self-test before the code did not pass
+        Day5.part1("104,1,104,0,99").unwrap();
+    }
+
+    #[test]
+    fn reads_each_input_in_turn() {
+        // Reads two numbers into [11] and [12], then outputs their sum
+        let rom = parse_rom("3,11,3,12,1,11,12,13,4,13,99,0,0,0").unwrap();
+        assert_eq!(Cpu::new(rom.to_vec()).run(&[3, 4]), vec![7]);
+        assert_eq!(Cpu::new(rom.to_vec()).run(&[-10, 4, 99]), vec![-6]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Missing input parameter")]
+    fn running_out_of_inputs_panics() {
+        Cpu::new(parse_rom("3,0,3,0,99").unwrap()).run(&[1]);
+    }
+
+    #[test]
+    fn failed_self_tests_have_no_diagnostic_code() {
+        assert_eq!(diagnostic_code(&[0, 0, 0, 1234]), Some(1234));
+        assert_eq!(diagnostic_code(&[42]), Some(42));
+        assert_eq!(diagnostic_code(&[0, 3, 0, 1234]), None);
+        assert_eq!(diagnostic_code(&[]), None);
+        let rom = parse_rom(&std::fs::read_to_string("input").unwrap()).unwrap();
+        let outputs = Cpu::new(rom).run(&[1]);
+        assert_eq!(diagnostic_code(&outputs), Some(7988899));
+    }
 }
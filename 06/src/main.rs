@@ -1,3 +1,4 @@
+use std::cell::RefCell;
 use std::collections::HashMap;
 
 #[derive(Debug)]
@@ -8,6 +9,10 @@ struct Orbit {
 
 fn parse_input(filename: &str) -> Vec<Orbit> {
     let input = std::fs::read_to_string(filename).unwrap();
+    parse_orbits(&input)
+}
+
+fn parse_orbits(input: &str) -> Vec<Orbit> {
     input
         .lines()
         .map(|line| {
@@ -20,77 +25,172 @@ fn parse_input(filename: &str) -> Vec<Orbit> {
         .collect()
 }
 
+#[derive(Debug)]
+enum PathError {
+    MissingPlanet(String),
+    NoCommonAncestor(String, String),
+}
+
+impl std::fmt::Display for PathError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PathError::MissingPlanet(name) => write!(f, "{} is not on the map", name),
+            PathError::NoCommonAncestor(a, b) => {
+                write!(f, "{} and {} don't orbit anything in common", a, b)
+            }
+        }
+    }
+}
+
 #[derive(Default)]
 struct Planet {
     parent: Option<String>,
     children: Vec<String>,
-    orbit_depth: usize,
 }
 
-struct PlanetMap(HashMap<String, Planet>);
+struct PlanetMap {
+    planets: HashMap<String, Planet>,
+    /// Orbit depths worked out so far, either all at once by `calculate_orbit_depth` or a chain at a time by
+    /// `depth_of`
+    depths: RefCell<HashMap<String, usize>>,
+}
 
 impl std::ops::Deref for PlanetMap {
     type Target = HashMap<String, Planet>;
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.planets
     }
 }
 
 impl std::ops::DerefMut for PlanetMap {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.0
+        &mut self.planets
     }
 }
 
 impl From<&str> for PlanetMap {
     fn from(filename: &str) -> PlanetMap {
-        let mut map = PlanetMap(HashMap::new());
-        for orbit in parse_input(filename) {
-            let child = map.entry(orbit.child.to_string()).or_default();
-            child.parent = Some(orbit.parent.to_string());
-            let planet = map.entry(orbit.parent.to_string()).or_default();
-            planet.children.push(orbit.child);
+        PlanetMap::from_orbits(parse_input(filename))
+    }
+}
+
+impl PlanetMap {
+    fn from_orbits(orbits: Vec<Orbit>) -> PlanetMap {
+        let mut map = PlanetMap::new();
+        for orbit in orbits {
+            map.add_orbit(orbit);
         }
 
         map.calculate_orbit_depth("COM");
         map
     }
-}
 
-impl PlanetMap {
-    fn calculate_orbit_depth(&mut self, planet: &str) {
-        fn add(planet: &str, map: &mut PlanetMap, depth: usize) {
-            let children: Vec<String> = map.get(planet).unwrap().children.to_vec();
-            for planet_name in &children {
-                let child = map.get_mut(planet_name).unwrap();
-                child.orbit_depth += depth;
-                add(planet_name, map, depth + 1);
+    fn new() -> PlanetMap {
+        PlanetMap {
+            planets: HashMap::new(),
+            depths: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Adds one orbit to the map. Any depths worked out so far could be stale now, so they're forgotten and worked out
+    /// again by `depth_of` as needed.
+    fn add_orbit(&mut self, orbit: Orbit) {
+        self.depths.borrow_mut().clear();
+        let child = self.entry(orbit.child.to_string()).or_default();
+        child.parent = Some(orbit.parent.to_string());
+        let planet = self.entry(orbit.parent).or_default();
+        planet.children.push(orbit.child);
+    }
+
+    /// Number of direct and indirect orbits of `planet`, found by climbing its parents instead of walking the whole tree
+    fn depth_of(&self, planet: &str) -> usize {
+        let mut cache = self.depths.borrow_mut();
+        let mut chain = vec![];
+        let mut current = planet;
+        let mut depth = loop {
+            if let Some(depth) = cache.get(current) {
+                break *depth;
+            }
+            match self.get(current).and_then(|p| p.parent.as_ref()) {
+                Some(parent) => {
+                    chain.push(current);
+                    current = parent;
+                }
+                None => {
+                    cache.insert(current.to_string(), 0);
+                    break 0;
+                }
             }
         };
-        add(planet, self, 1);
+        while let Some(name) = chain.pop() {
+            depth += 1;
+            cache.insert(name.to_string(), depth);
+        }
+        depth
+    }
+
+    /// Works out the depth of every planet orbiting `planet` in one walk down the tree
+    fn calculate_orbit_depth(&self, planet: &str) {
+        fn add(planet: &str, map: &PlanetMap, depths: &mut HashMap<String, usize>, depth: usize) {
+            for planet_name in &map.get(planet).unwrap().children {
+                depths.insert(planet_name.to_string(), depth);
+                add(planet_name, map, depths, depth + 1);
+            }
+        }
+        let mut depths = self.depths.borrow_mut();
+        depths.insert(planet.to_string(), 0);
+        add(planet, self, &mut depths, 1);
     }
 
     fn find_total_orbits(&self) -> usize {
+        self.keys().map(|name| self.depth_of(name)).sum()
+    }
+
+    #[allow(dead_code)]
+    fn count_direct(&self) -> usize {
         self.values()
-            .fold(0, |acc, planet| acc + planet.orbit_depth)
+            .filter(|planet| planet.parent.is_some())
+            .count()
     }
 
-    fn find_closest_path(&self, a: &str, b: &str) -> usize {
-        let mut a_search = self.get(a).unwrap();
-        let mut b_search = self.get(b).unwrap();
-        let a_depth = a_search.orbit_depth;
-        let b_depth = b_search.orbit_depth;
+    #[allow(dead_code)]
+    fn count_indirect(&self) -> usize {
+        self.find_total_orbits() - self.count_direct()
+    }
+
+    /// Deepest planet that both `a` and `b` orbit, directly or indirectly. `None` if either orbits nothing or they're
+    /// in separate trees.
+    fn common_ancestor(&self, a: &str, b: &str) -> Option<String> {
+        let parent = |name: &str| self.get(name)?.parent.as_ref();
+        let mut a_search = parent(a)?;
+        let mut b_search = parent(b)?;
 
-        while a_search.parent != b_search.parent {
-            if a_search.orbit_depth > b_search.orbit_depth {
-                a_search = self.get(a_search.parent.as_ref().unwrap()).unwrap();
+        while a_search != b_search {
+            if self.depth_of(a_search) > self.depth_of(b_search) {
+                a_search = parent(a_search)?;
             } else {
-                b_search = self.get(b_search.parent.as_ref().unwrap()).unwrap();
+                b_search = parent(b_search)?;
             }
         }
 
-        (a_depth - a_search.orbit_depth) + (b_depth - b_search.orbit_depth)
+        Some(a_search.clone())
+    }
+
+    fn find_closest_path(&self, a: &str, b: &str) -> Result<usize, PathError> {
+        let depth = |name: &str| match self.get(name) {
+            Some(_) => Ok(self.depth_of(name)),
+            None => Err(PathError::MissingPlanet(name.to_string())),
+        };
+        let a_depth = depth(a)?;
+        let b_depth = depth(b)?;
+        let ancestor = self
+            .common_ancestor(a, b)
+            .ok_or_else(|| PathError::NoCommonAncestor(a.to_string(), b.to_string()))?;
+        let ancestor_depth = self.depth_of(&ancestor);
+
+        // Transfers are between the planets being orbited, not the orbiting objects themselves
+        Ok((a_depth - ancestor_depth - 1) + (b_depth - ancestor_depth - 1))
     }
 }
 
@@ -98,5 +198,119 @@ fn main() {
     let map = PlanetMap::from("input");
 
     println!("6-1:\n{}", map.find_total_orbits());
-    println!("6-2:\n{}", map.find_closest_path("YOU", "SAN"));
+    let transfers = map
+        .find_closest_path("YOU", "SAN")
+        .unwrap_or_else(|e| panic!("Could not find path: {}", e));
+    println!("6-2:\n{}", transfers);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "COM)B
+B)C
+C)D
+D)E
+E)F
+B)G
+G)H
+D)I
+E)J
+J)K
+K)L
+";
+
+    fn example_map() -> PlanetMap {
+        PlanetMap::from_orbits(parse_orbits(EXAMPLE))
+    }
+
+    #[test]
+    fn direct_and_indirect_add_up_to_total() {
+        let map = example_map();
+        assert_eq!(map.count_direct(), 11);
+        assert_eq!(map.count_indirect(), 31);
+        assert_eq!(map.count_direct() + map.count_indirect(), 42);
+        assert_eq!(map.find_total_orbits(), 42);
+    }
+
+    #[test]
+    fn you_and_san_meet_at_d() {
+        let map = PlanetMap::from_orbits(parse_orbits(&format!("{}K)YOU\nI)SAN\n", EXAMPLE)));
+        assert_eq!(map.common_ancestor("YOU", "SAN").unwrap(), "D");
+        assert_eq!(map.common_ancestor("SAN", "YOU").unwrap(), "D");
+        assert_eq!(map.common_ancestor("H", "L").unwrap(), "B");
+        // Neither planet orbits itself, so a direct parent and child meet one level up
+        assert_eq!(map.common_ancestor("L", "K").unwrap(), "J");
+        assert_eq!(map.find_closest_path("YOU", "SAN").unwrap(), 4);
+    }
+
+    #[test]
+    fn missing_endpoints_are_named() {
+        let map = example_map();
+        assert!(matches!(
+            map.find_closest_path("YOU", "L"),
+            Err(PathError::MissingPlanet(ref name)) if name == "YOU"
+        ));
+        assert!(matches!(
+            map.find_closest_path("L", "SAN"),
+            Err(PathError::MissingPlanet(ref name)) if name == "SAN"
+        ));
+        assert_eq!(map.find_closest_path("L", "H").unwrap(), 6);
+    }
+
+    #[test]
+    fn depth_of_matches_orbit_depth() {
+        let eager = example_map();
+        assert_eq!(eager.depths.borrow().len(), eager.len());
+        assert_eq!(eager.depth_of("COM"), 0);
+        assert_eq!(eager.depth_of("L"), 7);
+
+        // Built back to front, so every early query sees a chain that gets longer later
+        let mut map = PlanetMap::new();
+        for orbit in parse_orbits(EXAMPLE).into_iter().rev() {
+            map.add_orbit(orbit);
+            assert_eq!(map.depth_of("L"), map.depth_of("K") + 1);
+        }
+        for name in eager.keys() {
+            assert_eq!(map.depth_of(name), eager.depth_of(name), "Planet {}", name);
+        }
+        assert_eq!(map.depth_of("H"), 3);
+        map.add_orbit(parse_orbits("L)YOU").pop().unwrap());
+        assert_eq!(map.depth_of("YOU"), 8);
+    }
+
+    #[test]
+    fn incrementally_built_maps_answer_both_parts() {
+        let mut map = PlanetMap::new();
+        for orbit in parse_orbits(&format!("{}K)YOU\nI)SAN\n", EXAMPLE)) {
+            map.add_orbit(orbit);
+        }
+        assert_eq!(map.find_total_orbits(), 42 + 7 + 5);
+        assert_eq!(map.common_ancestor("YOU", "SAN").unwrap(), "D");
+        assert_eq!(map.find_closest_path("YOU", "SAN").unwrap(), 4);
+    }
+
+    #[test]
+    fn roots_and_separate_trees_have_no_common_ancestor() {
+        let map = PlanetMap::from_orbits(parse_orbits(&format!("{}I)SAN\nX)Y\nY)Z\n", EXAMPLE)));
+        assert_eq!(map.common_ancestor("COM", "SAN"), None);
+        assert_eq!(map.common_ancestor("SAN", "COM"), None);
+        assert_eq!(map.common_ancestor("Z", "L"), None);
+        assert_eq!(map.common_ancestor("Y", "Z").unwrap(), "X");
+        assert_eq!(map.common_ancestor("Z", "Z").unwrap(), "Y");
+        match map.find_closest_path("COM", "SAN") {
+            Err(err @ PathError::NoCommonAncestor(..)) => {
+                assert_eq!(
+                    err.to_string(),
+                    "COM and SAN don't orbit anything in common"
+                )
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        assert!(matches!(
+            map.find_closest_path("Z", "SAN"),
+            Err(PathError::NoCommonAncestor(..))
+        ));
+    }
 }
@@ -23,6 +23,27 @@ fn permutations(input: Vec<i64>) -> Vec<Vec<i64>> {
     output
 }
 
+/// Thruster signal from running five amplifiers with the given phase settings in a feedback loop
+fn feedback_signal(rom: &[i64], phases: &[i64]) -> i64 {
+    let mut amplifiers: Vec<intcode::Cpu> = vec![intcode::Cpu::new(rom.to_vec()); 5];
+    let mut output = 0;
+    for i in 0..5 {
+        amplifiers[i].push(&vec![phases[i]]);
+    }
+    // Each amplifier suspends after producing an output, the loop ends once any of them halts
+    loop {
+        for i in 0..5 {
+            match amplifiers[i].next_output(&vec![output]) {
+                intcode::Poll::Result(out) => output = out,
+                intcode::Poll::Stop => return output,
+                intcode::Poll::NeedInput => panic!("Amplifier {} is missing input", i),
+                intcode::Poll::Fault(err) => panic!("Amplifier {} faulted: {}", i, err),
+                intcode::Poll::None => unreachable!(),
+            }
+        }
+    }
+}
+
 fn main() {
     let rom = intcode::parse_rom("input");
     println!("7-1:");
@@ -30,28 +51,45 @@ fn main() {
     for inputs in permutations((0..5).collect()) {
         let mut output = 0;
         for i in inputs {
-            output = intcode::Cpu::new(rom.to_vec()).run_with(&vec![i, output]).unwrap();
+            output = intcode::Cpu::with_inputs(rom.to_vec(), &[i, output]).run().unwrap();
         }
         max_output = std::cmp::max(max_output, output);
     }
     println!("{}", max_output);
     println!("7-2:");
-    let mut max_output = 0;
-    for inputs in permutations((5..10).collect()) {
-        let mut amplifiers: Vec<intcode::Cpu> = vec![intcode::Cpu::new(rom.to_vec()); 5];
-        let mut output = 0;
-        for i in 0..5 {
-            amplifiers[i].push(&vec![inputs[i]]);
-        }
-        'feedback_loop: loop {
-            for i in 0..5 {
-                match amplifiers[i].run_with(&vec![output]) {
-                    Some(out) => output = out,
-                    None => break 'feedback_loop,
-                }
-            }
-        }
-        max_output = std::cmp::max(max_output, output);
-    }
+    let max_output = permutations((5..10).collect())
+        .into_iter()
+        .map(|phases| feedback_signal(&rom, &phases))
+        .max()
+        .unwrap();
     println!("{}", max_output);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn feedback_loop_examples() {
+        let max_signal = |rom: &[i64]| {
+            permutations((5..10).collect())
+                .into_iter()
+                .map(|phases| feedback_signal(rom, &phases))
+                .max()
+                .unwrap()
+        };
+        let rom = vec![
+            3, 26, 1001, 26, -4, 26, 3, 27, 1002, 27, 2, 27, 1, 27, 26, 27, 4, 27, 1001, 28, -1, 28,
+            1005, 28, 6, 99, 0, 0, 5,
+        ];
+        assert_eq!(feedback_signal(&rom, &[9, 8, 7, 6, 5]), 139629729);
+        assert_eq!(max_signal(&rom), 139629729);
+        let rom = vec![
+            3, 52, 1001, 52, -5, 52, 3, 53, 1, 52, 56, 54, 1007, 54, 5, 55, 1005, 55, 26, 1001, 54,
+            -5, 54, 1105, 1, 12, 1, 53, 54, 53, 1008, 54, 0, 55, 1001, 55, 1, 55, 2, 53, 55, 53, 4,
+            53, 1001, 56, -1, 56, 1005, 56, 6, 99, 0, 0, 0, 0, 10,
+        ];
+        assert_eq!(feedback_signal(&rom, &[9, 7, 8, 5, 6]), 18216);
+        assert_eq!(max_signal(&rom), 18216);
+    }
+}
@@ -5,8 +5,46 @@ struct Image {
     layers: Vec<ImageLayer>,
 }
 
-fn parse_image(filename: &str, width: usize, height: usize) -> Image {
-    let mut data = String::from(std::fs::read_to_string(filename).unwrap().trim());
+#[derive(Debug)]
+enum ImageError {
+    ZeroSize { width: usize, height: usize },
+    LengthMismatch { len: usize, layer_size: usize },
+}
+
+impl std::fmt::Display for ImageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImageError::ZeroSize { width, height } => {
+                write!(f, "Image dimensions {}x{} have no pixels", width, height)
+            }
+            ImageError::LengthMismatch { len, layer_size } => write!(
+                f,
+                "Data length {} is not a multiple of the layer size {}",
+                len, layer_size
+            ),
+        }
+    }
+}
+
+fn parse_image(filename: &str, width: usize, height: usize) -> Result<Image, ImageError> {
+    parse_data(
+        std::fs::read_to_string(filename).unwrap().trim(),
+        width,
+        height,
+    )
+}
+
+fn parse_data(data: &str, width: usize, height: usize) -> Result<Image, ImageError> {
+    let mut data = String::from(data);
+    if width == 0 || height == 0 {
+        return Err(ImageError::ZeroSize { width, height });
+    }
+    if data.len() % (width * height) != 0 {
+        return Err(ImageError::LengthMismatch {
+            len: data.len(),
+            layer_size: width * height,
+        });
+    }
     let mut layers: Vec<ImageLayer> = vec![];
     while data.len() > 0 {
         let pixels: Vec<u8> = data
@@ -15,7 +53,7 @@ fn parse_image(filename: &str, width: usize, height: usize) -> Image {
             .collect();
         layers.push(ImageLayer { pixels });
     }
-    Image { layers }
+    Ok(Image { layers })
 }
 
 fn decode_image(image: &Image) -> ImageLayer {
@@ -49,8 +87,23 @@ fn find_least(image: &Image, n: u8) -> Option<&ImageLayer> {
     best_layer
 }
 
+#[allow(dead_code)]
+fn layer_histograms(image: &Image) -> Vec<[usize; 10]> {
+    image
+        .layers
+        .iter()
+        .map(|layer| {
+            let mut histogram = [0; 10];
+            for pixel in &layer.pixels {
+                histogram[*pixel as usize] += 1;
+            }
+            histogram
+        })
+        .collect()
+}
+
 fn main() {
-    let image = parse_image("input", 25, 6);
+    let image = parse_image("input", 25, 6).unwrap_or_else(|e| panic!("Invalid image: {}", e));
     println!("8-1:");
     let layer = find_least(&image, 0).unwrap();
     let verification_code = layer.pixels.iter().filter(|p| **p == 1).count()
@@ -71,3 +124,55 @@ fn main() {
         .collect::<String>();
     println!("{}", image_string);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histograms_count_every_digit() {
+        let image = Image {
+            layers: vec![
+                ImageLayer {
+                    pixels: vec![1, 2, 3, 4, 5, 6],
+                },
+                ImageLayer {
+                    pixels: vec![7, 8, 9, 0, 1, 2],
+                },
+                ImageLayer {
+                    pixels: vec![0, 2, 2, 1, 2, 2],
+                },
+            ],
+        };
+        let histograms = layer_histograms(&image);
+        assert_eq!(histograms.len(), 3);
+        assert_eq!(histograms[0], [0, 1, 1, 1, 1, 1, 1, 0, 0, 0]);
+        assert_eq!(histograms[1], [1, 1, 1, 0, 0, 0, 0, 1, 1, 1]);
+        assert_eq!(histograms[2], [1, 1, 4, 0, 0, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn dimensions_must_fit_the_data() {
+        let image = parse_data("123456789012", 3, 2).unwrap();
+        assert_eq!(image.layers.len(), 2);
+        assert_eq!(image.layers[1].pixels, vec![7, 8, 9, 0, 1, 2]);
+        assert!(matches!(
+            parse_data("1234567890", 3, 2),
+            Err(ImageError::LengthMismatch {
+                len: 10,
+                layer_size: 6
+            })
+        ));
+        assert!(matches!(
+            parse_data("123456", 0, 2),
+            Err(ImageError::ZeroSize {
+                width: 0,
+                height: 2
+            })
+        ));
+        assert!(matches!(
+            parse_data("123456", 3, 0),
+            Err(ImageError::ZeroSize { .. })
+        ));
+    }
+}
@@ -1,13 +1,53 @@
+extern crate day;
 extern crate intcode;
 
+struct Day9;
+
+impl Day9 {
+    fn boost(&self, input: &str, mode: i64) -> Result<String, intcode::LoadError> {
+        let mut cpu = intcode::Cpu::from_rom_str(input)?;
+        Ok(cpu
+            .run_with(&vec![mode])
+            .expect("BOOST halted without output")
+            .to_string())
+    }
+}
+
+impl day::Day for Day9 {
+    type Error = intcode::LoadError;
+
+    fn part1(&self, input: &str) -> Result<String, intcode::LoadError> {
+        self.boost(input, 1)
+    }
+
+    fn part2(&self, input: &str) -> Result<String, intcode::LoadError> {
+        self.boost(input, 2)
+    }
+}
+
 fn main() {
-    let rom = intcode::parse_rom("input");
-    println!(
-        "9-1:\n{}",
-        intcode::Cpu::new(rom.to_vec()).run_with(&vec![1]).unwrap()
-    );
-    println!(
-        "9-2:\n{}",
-        intcode::Cpu::new(rom.to_vec()).run_with(&vec![2]).unwrap()
-    );
+    day::run(9, &Day9, "input");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use day::Day;
+
+    #[test]
+    fn parts_against_fixtures() {
+        let input = std::fs::read_to_string("input").unwrap();
+        assert_eq!(
+            Day9.solve(&input).unwrap(),
+            ("3429606717".to_string(), "33679".to_string())
+        );
+        assert_eq!(
+            Day9.part1("104,1125899906842624,99").unwrap(),
+            "1125899906842624"
+        );
+        assert!(matches!(
+            Day9.part2("104,1,9x"),
+            Err(intcode::LoadError::Parse(_))
+        ));
+    }
 }
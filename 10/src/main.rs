@@ -1,3 +1,6 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, VecDeque};
+
 #[derive(Copy, Clone, Debug)]
 struct Position {
     x: i64,
@@ -24,22 +27,63 @@ fn greatest_common_factor(a: i64, b: i64) -> i64 {
     panic!("Could not find greatest common factor")
 }
 
-fn parse_asteroid_field(filename: &str) -> AsteroidField {
-    let mut field: AsteroidField = vec![];
+#[derive(Debug)]
+enum ParseError {
+    RaggedRow {
+        row: usize,
+        width: usize,
+        expected: usize,
+    },
+    UnexpectedChar {
+        row: usize,
+        c: char,
+    },
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::RaggedRow {
+                row,
+                width,
+                expected,
+            } => write!(f, "Row {} is {} wide, expected {}", row, width, expected),
+            ParseError::UnexpectedChar { row, c } => {
+                write!(f, "Row {} has unexpected tile '{}'", row, c)
+            }
+        }
+    }
+}
+
+fn parse_asteroid_field(filename: &str) -> Result<AsteroidField, ParseError> {
     let data = std::fs::read_to_string(filename).unwrap();
-    let mut line: Vec<bool> = vec![];
-    for c in data.chars() {
-        match c {
-            '#' => line.push(true),
-            '.' => line.push(false),
-            '\n' => {
-                field.push(line.to_vec());
-                line.clear();
+    parse_field(&data)
+}
+
+/// Fields have to be rectangular, everything past parsing indexes rows by the first row's width
+fn parse_field(data: &str) -> Result<AsteroidField, ParseError> {
+    let mut field: AsteroidField = vec![];
+    for (row, text) in data.lines().enumerate() {
+        let line = text
+            .chars()
+            .map(|c| match c {
+                '#' => Ok(true),
+                '.' => Ok(false),
+                _ => Err(ParseError::UnexpectedChar { row, c }),
+            })
+            .collect::<Result<Vec<bool>, ParseError>>()?;
+        if let Some(first) = field.first() {
+            if line.len() != first.len() {
+                return Err(ParseError::RaggedRow {
+                    row,
+                    width: line.len(),
+                    expected: first.len(),
+                });
             }
-            _ => panic!("Unknown input data"),
         }
+        field.push(line);
     }
-    field
+    Ok(field)
 }
 
 fn los(origin: Position, pos: Position, field: &AsteroidField) -> bool {
@@ -60,19 +104,6 @@ fn los(origin: Position, pos: Position, field: &AsteroidField) -> bool {
     true
 }
 
-fn rot(origin: Position, pos: Position) -> f64 {
-    let (dx, dy) = (
-        pos.x as f64 - origin.x as f64,
-        pos.y as f64 - origin.y as f64,
-    );
-    let rot = dy.atan2(dx) + std::f64::consts::PI / 2.0;
-    if rot < 0.0 {
-        rot + std::f64::consts::PI * 2.0
-    } else {
-        rot
-    }
-}
-
 fn count_visible_asteroids(x: i64, y: i64, field: &AsteroidField) -> usize {
     let mut count = 0;
     for (search_y, line) in field.iter().enumerate() {
@@ -95,66 +126,177 @@ fn count_visible_asteroids(x: i64, y: i64, field: &AsteroidField) -> usize {
     count
 }
 
-fn find_best_asteroid(field: &AsteroidField) -> Option<Asteroid> {
-    let mut best: Option<Asteroid> = None;
+fn visibility_map(field: &AsteroidField) -> Vec<(Position, usize)> {
+    let mut visibility = vec![];
     for (y, line) in field.iter().enumerate() {
         for (x, is_asteroid) in line.iter().enumerate() {
             if *is_asteroid {
                 let visible = count_visible_asteroids(x as i64, y as i64, field);
-                if best.is_none() || visible > best.unwrap().visible {
-                    best = Some(Asteroid {
-                        pos: Position {
-                            x: x as i64,
-                            y: y as i64,
-                        },
-                        visible,
-                    });
-                }
+                visibility.push((
+                    Position {
+                        x: x as i64,
+                        y: y as i64,
+                    },
+                    visible,
+                ));
             }
         }
     }
+    visibility
+}
+
+fn find_best_asteroid(field: &AsteroidField) -> Option<Asteroid> {
+    let mut best: Option<Asteroid> = None;
+    for (pos, visible) in visibility_map(field) {
+        if best.is_none() || visible > best.unwrap().visible {
+            best = Some(Asteroid { pos, visible });
+        }
+    }
     best
 }
 
-fn asteroid_destruction_queue(origin: Position, field: &AsteroidField) -> Vec<Asteroid> {
-    let mut destruction_queue: Vec<Asteroid> = vec![];
-    let mut next_field = field.to_vec();
-    loop {
-        let mut asteroids: Vec<Asteroid> = vec![];
-        let current_field = next_field.to_vec();
-        for (y, line) in current_field.iter().enumerate() {
-            for (x, is_asteroid) in line.iter().enumerate() {
-                if *is_asteroid {
-                    asteroids.push(Asteroid {
-                        pos: Position {
-                            x: x as i64,
-                            y: y as i64,
-                        },
-                        visible: 0,
-                    });
-                }
-            }
+/// Like `find_best_asteroid`, but only evaluates the given positions. Candidates that are not asteroids
+/// or lie outside the field are skipped.
+#[cfg(test)]
+fn best_among(field: &AsteroidField, candidates: &[Position]) -> Option<Asteroid> {
+    let mut best: Option<Asteroid> = None;
+    for pos in candidates {
+        if pos.x < 0 || pos.y < 0 {
+            continue;
         }
-        // Will not destroy origin base, should always have 1 left over
-        if asteroids.len() <= 1 {
-            break;
+        let tile = field
+            .get(pos.y as usize)
+            .and_then(|row| row.get(pos.x as usize));
+        if tile != Some(&true) {
+            continue;
         }
-        asteroids = asteroids
-            .into_iter()
-            .filter(|a| los(origin, a.pos, &current_field))
-            .collect::<Vec<_>>();
-        asteroids.sort_by(|a, b| rot(origin, a.pos).partial_cmp(&rot(origin, b.pos)).unwrap());
-        for asteroid in &asteroids {
-            destruction_queue.push(*asteroid);
-            next_field[asteroid.pos.y as usize][asteroid.pos.x as usize] = false;
+        let visible = count_visible_asteroids(pos.x, pos.y, field);
+        if best.is_none() || visible > best.unwrap().visible {
+            best = Some(Asteroid { pos: *pos, visible });
         }
     }
+    best
+}
+
+/// Bearing from an origin as a reduced step, ordered clockwise starting from straight up without any floating point
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct OrderedAngle {
+    dx: i64,
+    dy: i64,
+}
+
+impl OrderedAngle {
+    fn new(origin: Position, pos: Position) -> OrderedAngle {
+        let (dx, dy) = (pos.x - origin.x, pos.y - origin.y);
+        let factor = greatest_common_factor(dx, dy);
+        OrderedAngle {
+            dx: dx / factor,
+            dy: dy / factor,
+        }
+    }
+
+    /// Compass bearing in degrees, clockwise from straight up
+    #[cfg(test)]
+    fn degrees(&self) -> f64 {
+        let degrees = (self.dx as f64).atan2(-self.dy as f64).to_degrees();
+        if degrees < 0.0 {
+            degrees + 360.0
+        } else {
+            degrees
+        }
+    }
+
+    /// 0 for straight up and the right hand side, 1 for straight down and the left hand side
+    fn half(&self) -> u8 {
+        if self.dx > 0 || (self.dx == 0 && self.dy < 0) {
+            0
+        } else {
+            1
+        }
+    }
+}
+
+impl Ord for OrderedAngle {
+    fn cmp(&self, other: &OrderedAngle) -> Ordering {
+        // Within the same half a positive cross product means `other` is further clockwise (y points down)
+        self.half()
+            .cmp(&other.half())
+            .then_with(|| 0.cmp(&(self.dx * other.dy - self.dy * other.dx)))
+    }
+}
+
+impl PartialOrd for OrderedAngle {
+    fn partial_cmp(&self, other: &OrderedAngle) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Every asteroid other than `origin` grouped by bearing, each group sorted from nearest to furthest
+fn rays(origin: Position, field: &AsteroidField) -> BTreeMap<OrderedAngle, Vec<Asteroid>> {
+    let mut rays: BTreeMap<OrderedAngle, Vec<Asteroid>> = BTreeMap::new();
+    for (y, line) in field.iter().enumerate() {
+        for (x, is_asteroid) in line.iter().enumerate() {
+            let pos = Position {
+                x: x as i64,
+                y: y as i64,
+            };
+            if !*is_asteroid || (pos.x, pos.y) == (origin.x, origin.y) {
+                continue;
+            }
+            rays.entry(OrderedAngle::new(origin, pos))
+                .or_default()
+                .push(Asteroid { pos, visible: 0 });
+        }
+    }
+    for ray in rays.values_mut() {
+        ray.sort_by_key(|a| (a.pos.x - origin.x).abs() + (a.pos.y - origin.y).abs());
+    }
+    rays
+}
+
+/// How far in degrees a bearing can be from an asteroid and still hit it
+#[cfg(test)]
+const BEARING_TOLERANCE: f64 = 0.01;
+
+/// Nearest asteroid hit by pointing the laser at `degrees`, clockwise from straight up. Asteroids only sit at discrete
+/// bearings, so the closest ray within `BEARING_TOLERANCE` is used.
+#[cfg(test)]
+fn asteroid_at_bearing(origin: Position, field: &AsteroidField, degrees: f64) -> Option<Asteroid> {
+    let degrees = degrees.rem_euclid(360.0);
+    let error = |angle: &OrderedAngle| {
+        let diff = (angle.degrees() - degrees).abs();
+        diff.min(360.0 - diff)
+    };
+    rays(origin, field)
+        .into_iter()
+        .filter(|(angle, _)| error(angle) <= BEARING_TOLERANCE)
+        .min_by(|(a, _), (b, _)| error(a).partial_cmp(&error(b)).unwrap())
+        .map(|(_, ray)| ray[0])
+}
+
+/// Every asteroid in the order the laser at `origin` vaporizes it. The laser starts pointing straight up and turns
+/// clockwise, so within a rotation kills are in increasing bearing, and each rotation only reaches the nearest
+/// asteroid left on any bearing. The origin itself is never hit, so this holds one less than the number of asteroids.
+fn asteroid_destruction_queue(origin: Position, field: &AsteroidField) -> Vec<Asteroid> {
+    let mut destruction_queue: Vec<Asteroid> = vec![];
+    let mut rays = rays(origin, field)
+        .into_values()
+        .map(|ray| ray.into_iter().collect::<VecDeque<_>>())
+        .collect::<Vec<_>>();
+    // Each rotation of the laser takes out the nearest remaining asteroid on every ray
+    while !rays.is_empty() {
+        for ray in &mut rays {
+            destruction_queue.push(ray.pop_front().unwrap());
+        }
+        rays.retain(|ray| !ray.is_empty());
+    }
     destruction_queue
 }
 
 fn main() {
     println!("10-1:");
-    let field = parse_asteroid_field("input");
+    let field =
+        parse_asteroid_field("input").unwrap_or_else(|e| panic!("Invalid asteroid field: {}", e));
     let asteroid = find_best_asteroid(&field).unwrap();
     println!("{}", asteroid.visible);
     let winning_asteroid = asteroid_destruction_queue(asteroid.pos, &field)[199];
@@ -163,3 +305,165 @@ fn main() {
         winning_asteroid.pos.x * 100 + winning_asteroid.pos.y
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SMALL_EXAMPLE: &str = ".#..#
+.....
+#####
+....#
+...##
+";
+
+    const LARGE_EXAMPLE: &str = ".#..##.###...#######
+##.############..##.
+.#.######.########.#
+.###.#######.####.#.
+#####.##.#.##.###.##
+..#####..#.#########
+####################
+#.####....###.#.#.##
+##.#################
+#####.##.###..####..
+..######..##.#######
+####.##.####...##..#
+.#####..#.######.###
+##...#.##########...
+#.##########.#######
+.####.#.###.###.#.##
+....##.##.###..#####
+.#.#.###########.###
+#.#.#.#####.####.###
+###.##.####.##.#..##
+";
+
+    #[test]
+    fn visibility_map_agrees_with_best_asteroid() {
+        let field = parse_field(SMALL_EXAMPLE).unwrap();
+        let visibility = visibility_map(&field);
+        assert_eq!(visibility.len(), 10);
+        let (pos, visible) = visibility.iter().max_by_key(|(_, v)| *v).unwrap();
+        let best = find_best_asteroid(&field).unwrap();
+        assert_eq!((pos.x, pos.y, *visible), (3, 4, 8));
+        assert_eq!((best.pos.x, best.pos.y, best.visible), (3, 4, 8));
+        let (corner, visible) = visibility[0];
+        assert_eq!((corner.x, corner.y, visible), (1, 0, 7));
+    }
+
+    #[test]
+    fn best_among_all_asteroids_matches_best() {
+        let field = parse_field(SMALL_EXAMPLE).unwrap();
+        let asteroids: Vec<Position> = visibility_map(&field).iter().map(|(pos, _)| *pos).collect();
+        let best = best_among(&field, &asteroids).unwrap();
+        assert_eq!((best.pos.x, best.pos.y, best.visible), (3, 4, 8));
+
+        let strays = [
+            Position { x: 0, y: 0 },
+            Position { x: -1, y: 2 },
+            Position { x: 2, y: -1 },
+            Position { x: 5, y: 2 },
+            Position { x: 0, y: 9 },
+        ];
+        assert!(best_among(&field, &strays).is_none());
+        let mut mixed = strays.to_vec();
+        mixed.push(Position { x: 1, y: 0 });
+        let best = best_among(&field, &mixed).unwrap();
+        assert_eq!((best.pos.x, best.pos.y, best.visible), (1, 0, 7));
+    }
+
+    #[test]
+    fn one_ray_per_visible_asteroid() {
+        for (example, visible) in [(SMALL_EXAMPLE, 8), (LARGE_EXAMPLE, 210)].iter() {
+            let field = parse_field(example).unwrap();
+            let best = find_best_asteroid(&field).unwrap();
+            assert_eq!(best.visible, *visible);
+            let rays = rays(best.pos, &field);
+            assert_eq!(rays.len(), best.visible);
+            for ray in rays.values() {
+                let distances = ray
+                    .iter()
+                    .map(|a| (a.pos.x - best.pos.x).abs() + (a.pos.y - best.pos.y).abs())
+                    .collect::<Vec<_>>();
+                assert!(distances.windows(2).all(|pair| pair[0] < pair[1]));
+            }
+        }
+        let field = parse_field(LARGE_EXAMPLE).unwrap();
+        let first = rays(Position { x: 11, y: 13 }, &field);
+        let (up, ray) = first.iter().next().unwrap();
+        assert_eq!((up.dx, up.dy), (0, -1));
+        assert_eq!((ray[0].pos.x, ray[0].pos.y), (11, 12));
+    }
+
+    #[test]
+    fn asteroid_at_compass_bearings() {
+        let field = parse_field("..#..\n..#..\n..#.#\n.....\n#....\n").unwrap();
+        let origin = Position { x: 2, y: 2 };
+        let hit =
+            |degrees| asteroid_at_bearing(origin, &field, degrees).map(|a| (a.pos.x, a.pos.y));
+        // Straight up hits the nearer of the two stacked asteroids
+        assert_eq!(hit(0.0), Some((2, 1)));
+        assert_eq!(hit(360.0), Some((2, 1)));
+        assert_eq!(hit(90.0), Some((4, 2)));
+        assert_eq!(hit(225.0), Some((0, 4)));
+        assert_eq!(hit(-135.0), Some((0, 4)));
+        assert_eq!(hit(45.0), None);
+        assert_eq!(hit(0.5), None);
+    }
+
+    #[test]
+    fn vaporization_order_on_large_example() {
+        let field = parse_field(LARGE_EXAMPLE).unwrap();
+        let origin = Position { x: 11, y: 13 };
+        let queue = asteroid_destruction_queue(origin, &field);
+        let kills = [
+            (1, (11, 12)),
+            (2, (12, 1)),
+            (3, (12, 2)),
+            (10, (12, 8)),
+            (20, (16, 0)),
+            (50, (16, 9)),
+            (100, (10, 16)),
+            (199, (9, 6)),
+            (200, (8, 2)),
+            (201, (10, 9)),
+            (299, (11, 1)),
+        ];
+        for (kill, pos) in kills.iter() {
+            let asteroid = queue[kill - 1];
+            assert_eq!((asteroid.pos.x, asteroid.pos.y), *pos, "Kill {}", kill);
+        }
+        assert_eq!(
+            queue.len() + 1,
+            field.iter().flatten().filter(|a| **a).count()
+        );
+        assert_eq!(queue.len(), 299);
+        // The first rotation takes one asteroid from each of the 210 visible bearings, in clockwise order
+        let bearings = queue[..210]
+            .iter()
+            .map(|a| OrderedAngle::new(origin, a.pos))
+            .collect::<Vec<_>>();
+        assert!(bearings.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(OrderedAngle::new(origin, queue[210].pos) < bearings[209]);
+    }
+
+    #[test]
+    fn ragged_and_unknown_tiles_are_rejected() {
+        match parse_field(".#..#\n.....\n###\n....#\n") {
+            Err(err @ ParseError::RaggedRow { .. }) => {
+                assert_eq!(err.to_string(), "Row 2 is 3 wide, expected 5")
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        match parse_field(".#..#\n..X..\n") {
+            Err(err @ ParseError::UnexpectedChar { .. }) => {
+                assert_eq!(err.to_string(), "Row 1 has unexpected tile 'X'")
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        let field = parse_field(".#..#\n.....\n").unwrap();
+        assert_eq!(field.len(), 2);
+        assert!(field.iter().all(|row| row.len() == 5));
+    }
+}
@@ -1,8 +1,9 @@
+extern crate day;
 extern crate intcode;
 
+use day::direction::{Direction, Position};
 use std::cmp::{max, min};
-
-type Position = (i64, i64);
+use std::collections::HashSet;
 
 struct BoundingBox {
     min: Position,
@@ -15,66 +16,97 @@ enum HullColour {
     WHITE,
 }
 
-#[derive(Debug, PartialEq)]
-enum Direction {
-    UP,
-    DOWN,
-    LEFT,
-    RIGHT,
+#[derive(Debug)]
+enum RobotError {
+    UnexpectedColour { value: i64, step: usize },
+    UnexpectedDirection { value: i64, step: usize },
+    StepLimitExceeded(usize),
 }
 
-impl Direction {
-    fn turn(&self, direction: Direction) -> Direction {
-        match direction {
-            Direction::LEFT if *self == Direction::UP => Direction::LEFT,
-            Direction::LEFT if *self == Direction::LEFT => Direction::DOWN,
-            Direction::LEFT if *self == Direction::DOWN => Direction::RIGHT,
-            Direction::LEFT if *self == Direction::RIGHT => Direction::UP,
-            Direction::RIGHT if *self == Direction::UP => Direction::RIGHT,
-            Direction::RIGHT if *self == Direction::RIGHT => Direction::DOWN,
-            Direction::RIGHT if *self == Direction::DOWN => Direction::LEFT,
-            Direction::RIGHT if *self == Direction::LEFT => Direction::UP,
-            _ => panic!("Unexpected direction output"),
+impl std::fmt::Display for RobotError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RobotError::UnexpectedColour { value, step } => {
+                write!(f, "Unexpected colour output {} at step {}", value, step)
+            }
+            RobotError::UnexpectedDirection { value, step } => {
+                write!(f, "Unexpected direction output {} at step {}", value, step)
+            }
+            RobotError::StepLimitExceeded(steps) => {
+                write!(f, "Still painting after {} steps", steps)
+            }
         }
     }
 }
 
-struct EmergencyHullPaintingRobot {
-    cpu: intcode::Cpu,
+/// Whatever drives the robot. Given the colour under the robot, returns the (colour, direction) outputs or `None` once
+/// it's done painting.
+trait Brain {
+    fn decide(&mut self, input: i64) -> Option<(i64, i64)>;
+}
+
+impl Brain for intcode::Cpu {
+    fn decide(&mut self, input: i64) -> Option<(i64, i64)> {
+        match self.run_n_outputs(2, &vec![input])[..] {
+            [colour, direction] => Some((colour, direction)),
+            _ => None,
+        }
+    }
+}
+
+struct EmergencyHullPaintingRobot<B: Brain> {
+    brain: B,
     painted_tiles: std::collections::HashMap<Position, HullColour>,
     direction: Direction,
     pos: Position,
 }
 
-impl EmergencyHullPaintingRobot {
-    fn new(rom: Vec<i64>) -> EmergencyHullPaintingRobot {
+impl EmergencyHullPaintingRobot<intcode::Cpu> {
+    fn new(rom: Vec<i64>) -> EmergencyHullPaintingRobot<intcode::Cpu> {
+        EmergencyHullPaintingRobot::with_brain(intcode::Cpu::new(rom))
+    }
+}
+
+impl<B: Brain> EmergencyHullPaintingRobot<B> {
+    fn with_brain(brain: B) -> EmergencyHullPaintingRobot<B> {
         EmergencyHullPaintingRobot {
-            cpu: intcode::Cpu::new(rom),
+            brain,
             painted_tiles: std::collections::HashMap::new(),
-            direction: Direction::UP,
+            direction: Direction::Up,
             pos: (0, 0),
         }
     }
 
+    /// Positions currently painted white, which is all the registration identifier is made of
+    fn white_tiles(&self) -> HashSet<Position> {
+        self.painted_tiles
+            .iter()
+            .filter(|(_, colour)| **colour == HullColour::WHITE)
+            .map(|(pos, _)| *pos)
+            .collect()
+    }
+
     fn print_tiles(&self) {
+        let white_tiles = &self.white_tiles();
         let mut bounds = BoundingBox {
             min: self.pos,
             max: self.pos,
         };
-        for tile in &self.painted_tiles {
-            if *tile.1 == HullColour::WHITE {
-                bounds.min = (min(bounds.min.0, (tile.0).0), min(bounds.min.1, (tile.0).1));
-                bounds.max = (max(bounds.max.0, (tile.0).0), max(bounds.max.1, (tile.0).1));
-            }
+        for tile in white_tiles {
+            bounds.min = (min(bounds.min.0, tile.0), min(bounds.min.1, tile.1));
+            bounds.max = (max(bounds.max.0, tile.0), max(bounds.max.1, tile.1));
         }
         // Ok, well, a nested loop would look way nicer than this monstrosity
         // But it's functional so that means this is actually amazing
         let tile_string = (bounds.min.1..=bounds.max.1)
             .flat_map(|y| {
                 (bounds.min.0..=bounds.max.0)
-                    .map(move |x| match self.painted_tiles.get(&(x, y)) {
-                        Some(HullColour::WHITE) => '#',
-                        _ => '.',
+                    .map(move |x| {
+                        if white_tiles.contains(&(x, y)) {
+                            '#'
+                        } else {
+                            '.'
+                        }
                     })
                     .chain(std::iter::once('\n'))
             })
@@ -82,8 +114,9 @@ impl EmergencyHullPaintingRobot {
         print!("{}", tile_string);
     }
 
-    fn run(&mut self) {
-        loop {
+    /// Paints until the brain is done, or gives up after `max_steps` paint cycles if given
+    fn run(&mut self, max_steps: Option<usize>) -> Result<(), RobotError> {
+        for step in 0.. {
             let tile = self
                 .painted_tiles
                 .entry(self.pos)
@@ -92,43 +125,145 @@ impl EmergencyHullPaintingRobot {
                 HullColour::BLACK => 0,
                 HullColour::WHITE => 1,
             };
-            let colour = match self.cpu.run_with(&vec![input]) {
-                Some(value) => value,
-                None => return,
-            };
-            let direction = match self.cpu.run() {
-                Some(value) => value,
-                None => return,
+            let (colour, direction) = match self.brain.decide(input) {
+                Some(outputs) => outputs,
+                None => return Ok(()),
             };
+            // Only counts as too many once the brain asks for another cycle, halting right on the limit is fine
+            if Some(step) == max_steps {
+                return Err(RobotError::StepLimitExceeded(step));
+            }
             *tile = match colour {
                 0 => HullColour::BLACK,
                 1 => HullColour::WHITE,
-                _ => panic!("Unexpected colour output"),
+                value => return Err(RobotError::UnexpectedColour { value, step }),
             };
-            self.direction = self.direction.turn(match direction {
-                0 => Direction::LEFT,
-                1 => Direction::RIGHT,
-                _ => panic!("Unexpected direction output"),
-            });
-            self.pos = match self.direction {
-                Direction::UP => (self.pos.0, self.pos.1 - 1),
-                Direction::RIGHT => (self.pos.0 + 1, self.pos.1),
-                Direction::LEFT => (self.pos.0 - 1, self.pos.1),
-                Direction::DOWN => (self.pos.0, self.pos.1 + 1),
+            self.direction = match direction {
+                0 => self.direction.turn_left(),
+                1 => self.direction.turn_right(),
+                value => return Err(RobotError::UnexpectedDirection { value, step }),
             };
+            self.pos = self.direction.step(self.pos);
         }
+        Ok(())
     }
 }
 
+/// Far more paint cycles than the real program needs, anything past this is stuck in a loop
+const MAX_STEPS: usize = 100_000;
+
 fn main() {
     let rom = intcode::parse_rom("input");
     println!("11-1:");
     let mut robot = EmergencyHullPaintingRobot::new(rom.to_vec());
-    robot.run();
+    robot
+        .run(Some(MAX_STEPS))
+        .unwrap_or_else(|e| panic!("{}", e));
     println!("{}", robot.painted_tiles.len());
     println!("11-2:");
     robot = EmergencyHullPaintingRobot::new(rom.to_vec());
     robot.painted_tiles.insert(robot.pos, HullColour::WHITE);
-    robot.run();
+    robot
+        .run(Some(MAX_STEPS))
+        .unwrap_or_else(|e| panic!("{}", e));
     robot.print_tiles();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    /// Replays fixed outputs, keeping track of the colours it was shown
+    struct ScriptedBrain {
+        outputs: VecDeque<(i64, i64)>,
+        inputs: Vec<i64>,
+    }
+
+    impl Brain for ScriptedBrain {
+        fn decide(&mut self, input: i64) -> Option<(i64, i64)> {
+            self.inputs.push(input);
+            self.outputs.pop_front()
+        }
+    }
+
+    #[test]
+    fn scripted_brain_paints_example() {
+        let outputs = vec![(1, 0), (0, 0), (1, 0), (1, 0), (0, 1), (1, 0), (1, 0)];
+        let mut robot = EmergencyHullPaintingRobot::with_brain(ScriptedBrain {
+            outputs: outputs.into_iter().collect(),
+            inputs: vec![],
+        });
+        robot.run(Some(MAX_STEPS)).unwrap();
+        // The only revisit is the starting panel, which was painted white on the first step
+        assert_eq!(robot.brain.inputs, vec![0, 0, 0, 0, 1, 0, 0, 0]);
+        let up = Direction::Up.step((0, 0));
+        let down = Direction::Down.step((0, 0));
+        // Six panels painted, plus the one it stopped on which gets read as black before the brain runs out
+        assert_eq!(robot.painted_tiles.len(), 7);
+        let expected: HashSet<Position> = vec![(-1, down.1), (0, down.1), (1, 0), (1, up.1)]
+            .into_iter()
+            .collect();
+        assert_eq!(robot.white_tiles(), expected);
+        assert_eq!(robot.pos, (0, up.1));
+        assert_eq!(robot.direction, Direction::Left);
+    }
+
+    #[test]
+    fn bad_outputs_are_errors() {
+        let mut robot = EmergencyHullPaintingRobot::new(vec![3, 100, 104, 2, 104, 0, 99]);
+        assert!(matches!(
+            robot.run(None),
+            Err(RobotError::UnexpectedColour { value: 2, step: 0 })
+        ));
+        let mut robot = EmergencyHullPaintingRobot::new(vec![3, 100, 104, 1, 104, 5, 99]);
+        assert!(matches!(
+            robot.run(None),
+            Err(RobotError::UnexpectedDirection { value: 5, step: 0 })
+        ));
+    }
+
+    #[test]
+    fn step_limit_stops_endless_painting() {
+        let scripted = || {
+            EmergencyHullPaintingRobot::with_brain(ScriptedBrain {
+                outputs: vec![(1, 0); 7].into_iter().collect(),
+                inputs: vec![],
+            })
+        };
+        // Halting straight after the last allowed cycle isn't a loop
+        assert!(scripted().run(Some(7)).is_ok());
+        assert!(matches!(
+            scripted().run(Some(6)),
+            Err(RobotError::StepLimitExceeded(6))
+        ));
+
+        // Paints white and turns left forever
+        let mut robot = EmergencyHullPaintingRobot::new(vec![3, 100, 104, 1, 104, 0, 1105, 1, 0]);
+        assert!(matches!(
+            robot.run(Some(1000)),
+            Err(RobotError::StepLimitExceeded(1000))
+        ));
+        assert_eq!(robot.painted_tiles.len(), 4);
+    }
+
+    #[test]
+    fn repainting_black_clears_a_white_tile() {
+        // Paints a white square turning right each time, then paints the start black again once back on it
+        let outputs = vec![(1, 1), (1, 1), (1, 1), (1, 1), (0, 0)];
+        let mut robot = EmergencyHullPaintingRobot::with_brain(ScriptedBrain {
+            outputs: outputs.into_iter().collect(),
+            inputs: vec![],
+        });
+        assert!(robot.white_tiles().is_empty());
+        robot.run(Some(MAX_STEPS)).unwrap();
+        assert_eq!(robot.brain.inputs[..5], [0, 0, 0, 0, 1]);
+        let right = Direction::Right.step((0, 0));
+        let down = Direction::Down.step((0, 0));
+        let expected: HashSet<Position> =
+            vec![right, (right.0, down.1), down].into_iter().collect();
+        assert_eq!(robot.white_tiles(), expected);
+        // The square plus the black panel it stopped on
+        assert_eq!(robot.painted_tiles.len(), 5);
+    }
+}
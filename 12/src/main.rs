@@ -27,15 +27,24 @@ impl Moon {
         }
     }
 
+    fn potential(&self) -> i64 {
+        self.pos.x.abs() + self.pos.y.abs() + self.pos.z.abs()
+    }
+
+    fn kinetic(&self) -> i64 {
+        self.velocity.x.abs() + self.velocity.y.abs() + self.velocity.z.abs()
+    }
+
     fn energy(&self) -> i64 {
-        let potential = self.pos.x.abs() + self.pos.y.abs() + self.pos.z.abs();
-        let kinetic = self.velocity.x.abs() + self.velocity.y.abs() + self.velocity.z.abs();
-        potential * kinetic
+        self.potential() * self.kinetic()
     }
 }
 
 fn parse_input(filename: &str) -> Vec<Moon> {
-    let input = std::fs::read_to_string(filename).unwrap();
+    parse_moons(&std::fs::read_to_string(filename).unwrap())
+}
+
+fn parse_moons(input: &str) -> Vec<Moon> {
     let mut moons: Vec<Moon> = vec![];
     fn find_between<'a>(search: &'a str, start: &str, end: &str) -> &'a str {
         let start_i = search.find(start).unwrap() + start.len();
@@ -52,24 +61,35 @@ fn parse_input(filename: &str) -> Vec<Moon> {
     moons
 }
 
-fn step(moons: &Vec<Moon>) -> Vec<Moon> {
-    let mut next_state = moons.to_vec();
-    fn velocity_cmp(a: &i64, b: &i64) -> i64 {
-        match a.partial_cmp(&b) {
-            Some(std::cmp::Ordering::Equal) => 0,
-            Some(std::cmp::Ordering::Less) => 1,
-            Some(std::cmp::Ordering::Greater) => -1,
-            None => panic!("Unexpected order"),
+/// Velocity change gravity applies to a moon at `a` from one at `b`. The moon at `b` gets the opposite change.
+fn gravity(a: Vec3, b: Vec3) -> Vec3 {
+    fn pull(a: i64, b: i64) -> i64 {
+        match a.cmp(&b) {
+            std::cmp::Ordering::Equal => 0,
+            std::cmp::Ordering::Less => 1,
+            std::cmp::Ordering::Greater => -1,
         }
     }
+    Vec3 {
+        x: pull(a.x, b.x),
+        y: pull(a.y, b.y),
+        z: pull(a.z, b.z),
+    }
+}
+
+/// Next state of every moon after one time step, leaving `moons` as it was
+#[allow(dead_code)]
+fn step(moons: &[Moon]) -> Vec<Moon> {
+    let mut next_state = moons.to_vec();
     for (i, moon) in next_state.iter_mut().enumerate() {
         for (j, neighbour) in moons.iter().enumerate() {
             if i == j {
                 continue;
             }
-            moon.velocity.x += velocity_cmp(&moon.pos.x, &neighbour.pos.x);
-            moon.velocity.y += velocity_cmp(&moon.pos.y, &neighbour.pos.y);
-            moon.velocity.z += velocity_cmp(&moon.pos.z, &neighbour.pos.z);
+            let delta = gravity(moon.pos, neighbour.pos);
+            moon.velocity.x += delta.x;
+            moon.velocity.y += delta.y;
+            moon.velocity.z += delta.z;
         }
     }
     for moon in &mut next_state {
@@ -80,6 +100,46 @@ fn step(moons: &Vec<Moon>) -> Vec<Moon> {
     next_state
 }
 
+/// Advances every moon by one time step in place. Gravity only reads positions, so velocities can be updated pairwise
+/// before any moon moves, without needing a copy of the previous state.
+fn step_mut(moons: &mut [Moon]) {
+    for i in 0..moons.len() {
+        for j in i + 1..moons.len() {
+            let delta = gravity(moons[i].pos, moons[j].pos);
+            moons[i].velocity.x += delta.x;
+            moons[i].velocity.y += delta.y;
+            moons[i].velocity.z += delta.z;
+            moons[j].velocity.x -= delta.x;
+            moons[j].velocity.y -= delta.y;
+            moons[j].velocity.z -= delta.z;
+        }
+    }
+    for moon in moons.iter_mut() {
+        moon.pos.x += moon.velocity.x;
+        moon.pos.y += moon.velocity.y;
+        moon.pos.z += moon.velocity.z;
+    }
+}
+
+/// Velocity change from each pair of moons, keyed by their indices
+#[cfg(test)]
+type GravityTrace = Vec<((usize, usize), Vec3)>;
+
+/// Debugging version of `step_mut` that also reports each pair's gravity, as the velocity change applied to the first
+/// moon of the pair. The second moon always gets the opposite change.
+#[cfg(test)]
+fn step_traced(moons: &[Moon]) -> (Vec<Moon>, GravityTrace) {
+    let mut contributions = vec![];
+    for i in 0..moons.len() {
+        for j in i + 1..moons.len() {
+            contributions.push(((i, j), gravity(moons[i].pos, moons[j].pos)));
+        }
+    }
+    let mut next_state = moons.to_vec();
+    step_mut(&mut next_state);
+    (next_state, contributions)
+}
+
 fn gcd(a: usize, b: usize) -> usize {
     match b {
         0 => a,
@@ -116,7 +176,7 @@ macro_rules! find_axis_loop {
                 };
 
                 iteration_cache.insert(iteration);
-                moons = step(&moons);
+                step_mut(&mut moons);
             }
             iteration_counts.push(iteration_cache.len());
         )+
@@ -124,16 +184,102 @@ macro_rules! find_axis_loop {
     }};
 }
 
+/// Steps until each axis first repeats, as (x, y, z). The whole system repeats after their LCM.
+fn axis_cycles(moons: &[Moon]) -> (usize, usize, usize) {
+    match find_axis_loop!(moons, x, y, z)[..] {
+        [x, y, z] => (x, y, z),
+        _ => unreachable!(),
+    }
+}
+
 fn main() {
     let moons = parse_input("input");
 
     println!("12-1:");
     let mut moon_energy = moons.to_vec();
     for _ in 0..1000 {
-        moon_energy = step(&moon_energy);
+        step_mut(&mut moon_energy);
     }
     println!("{}", moon_energy.iter().map(|m| m.energy()).sum::<i64>());
     println!("12-2:");
-    let iteration_counts = find_axis_loop!(moons, x, y, z);
-    println!("{}", lcm_slice(&iteration_counts));
+    let (x, y, z) = axis_cycles(&moons);
+    println!("{}", lcm_slice(&[x, y, z]));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn energy_components() {
+        let moon = Moon {
+            pos: Vec3 { x: 2, y: 1, z: -3 },
+            velocity: Vec3 { x: -3, y: -2, z: 1 },
+        };
+        assert_eq!(moon.potential(), 6);
+        assert_eq!(moon.kinetic(), 6);
+        assert_eq!(moon.energy(), 36);
+        assert_eq!(Moon::new().energy(), 0);
+    }
+
+    const EXAMPLE: &str = "<x=-1, y=0, z=2>
+<x=2, y=-10, z=-7>
+<x=4, y=-8, z=8>
+<x=3, y=5, z=-1>
+";
+
+    #[test]
+    fn step_mut_follows_example() {
+        let mut moons = parse_moons(EXAMPLE);
+        step_mut(&mut moons);
+        assert_eq!(moons[0].pos, Vec3 { x: 2, y: -1, z: 1 });
+        assert_eq!(moons[0].velocity, Vec3 { x: 3, y: -1, z: -1 });
+        assert_eq!(moons[3].pos, Vec3 { x: 2, y: 2, z: 0 });
+        assert_eq!(moons[3].velocity, Vec3 { x: -1, y: -3, z: 1 });
+        for _ in 1..10 {
+            step_mut(&mut moons);
+        }
+        assert_eq!(moons[0].pos, Vec3 { x: 2, y: 1, z: -3 });
+        assert_eq!(moons[0].velocity, Vec3 { x: -3, y: -2, z: 1 });
+        assert_eq!(moons.iter().map(|m| m.energy()).sum::<i64>(), 179);
+    }
+
+    #[test]
+    fn traced_step_reports_each_pair() {
+        let moons = parse_moons("<x=0, y=5, z=3>\n<x=2, y=1, z=3>\n");
+        let (next_state, trace) = step_traced(&moons);
+        assert_eq!(trace, vec![((0, 1), Vec3 { x: 1, y: -1, z: 0 })]);
+        assert_eq!(next_state[0].pos, Vec3 { x: 1, y: 4, z: 3 });
+        assert_eq!(next_state[1].velocity, Vec3 { x: -1, y: 1, z: 0 });
+
+        let moons = parse_moons(EXAMPLE);
+        let (next_state, trace) = step_traced(&moons);
+        let mut expected = moons.to_vec();
+        step_mut(&mut expected);
+        assert_eq!(next_state, expected);
+        assert_eq!(trace.len(), 6);
+        assert_eq!(trace[0], ((0, 1), Vec3 { x: 1, y: -1, z: -1 }));
+    }
+
+    #[test]
+    fn axis_cycles_on_example() {
+        let (x, y, z) = axis_cycles(&parse_moons(EXAMPLE));
+        assert_eq!((x, y, z), (18, 28, 44));
+        assert_eq!(lcm_slice(&[x, y, z]), 2772);
+    }
+
+    #[test]
+    fn step_mut_matches_step() {
+        let input = std::fs::read_to_string("input").unwrap();
+        for moons in [parse_moons(EXAMPLE), parse_moons(&input)].iter() {
+            let mut in_place = moons.to_vec();
+            let mut functional = moons.to_vec();
+            for tick in 1..=100 {
+                step_mut(&mut in_place);
+                functional = step(&functional);
+                assert_eq!(in_place, functional, "Tick {}", tick);
+            }
+            assert_ne!(&functional, moons);
+        }
+    }
 }
@@ -1,7 +1,8 @@
 extern crate intcode;
 
 use std::cmp::{max, min};
-use std::collections::HashSet;
+use std::collections::HashMap;
+use std::convert::TryFrom;
 
 type Position = (i64, i64);
 
@@ -14,19 +15,30 @@ enum Tile {
     BALL,
 }
 
-impl Tile {
-    fn from(i: i64) -> Tile {
+#[derive(Debug)]
+struct InvalidTile(i64);
+
+impl TryFrom<i64> for Tile {
+    type Error = InvalidTile;
+
+    fn try_from(i: i64) -> Result<Tile, InvalidTile> {
         match i {
-            0 => Tile::EMPTY,
-            1 => Tile::WALL,
-            2 => Tile::BLOCK,
-            3 => Tile::HORIPAD,
-            4 => Tile::BALL,
-            _ => panic!("Invalid tile type"),
+            0 => Ok(Tile::EMPTY),
+            1 => Ok(Tile::WALL),
+            2 => Ok(Tile::BLOCK),
+            3 => Ok(Tile::HORIPAD),
+            4 => Ok(Tile::BALL),
+            _ => Err(InvalidTile(i)),
         }
     }
 }
 
+impl Tile {
+    fn from(i: i64) -> Tile {
+        Tile::try_from(i).unwrap_or_else(|InvalidTile(i)| panic!("Invalid tile type {:?}", i))
+    }
+}
+
 #[derive(Copy, Clone, Debug, PartialEq)]
 enum Input {
     NONE,
@@ -44,12 +56,118 @@ impl Input {
     }
 }
 
-type Screen = std::collections::HashMap<Position, Tile>;
+struct Screen {
+    tiles: HashMap<Position, Tile>,
+    ball_pos: Option<Position>,
+    paddle_pos: Option<Position>,
+    block_count: usize,
+    last_broken_block: Option<Position>,
+}
+
+impl Screen {
+    fn new() -> Screen {
+        Screen {
+            tiles: HashMap::new(),
+            ball_pos: None,
+            paddle_pos: None,
+            block_count: 0,
+            last_broken_block: None,
+        }
+    }
+
+    fn insert(&mut self, pos: Position, tile: Tile) {
+        // Keep cached positions in sync so the AI never has to scan the whole screen
+        let previous = self.tiles.insert(pos, tile);
+        if previous == Some(tile) {
+            return;
+        }
+        match previous {
+            Some(Tile::BLOCK) => {
+                self.block_count -= 1;
+                self.last_broken_block = Some(pos);
+            }
+            Some(Tile::BALL) if self.ball_pos == Some(pos) => self.ball_pos = None,
+            Some(Tile::HORIPAD) if self.paddle_pos == Some(pos) => self.paddle_pos = None,
+            _ => {}
+        }
+        match tile {
+            Tile::BLOCK => self.block_count += 1,
+            Tile::BALL => self.ball_pos = Some(pos),
+            Tile::HORIPAD => self.paddle_pos = Some(pos),
+            _ => {}
+        }
+    }
+
+    fn get(&self, pos: &Position) -> Option<&Tile> {
+        self.tiles.get(pos)
+    }
+
+    fn ball_pos(&self) -> Option<Position> {
+        self.ball_pos
+    }
+
+    fn paddle_pos(&self) -> Option<Position> {
+        self.paddle_pos
+    }
+
+    fn block_count(&self) -> usize {
+        self.block_count
+    }
+
+    fn last_broken_block(&self) -> Option<Position> {
+        self.last_broken_block
+    }
+}
+
+/// A complete draw instruction from the game
+enum Frame {
+    Draw(Position, Tile),
+    Score(i64),
+}
+
+/// Assembles the game's outputs into frames one value at a time
+struct FrameAssembler {
+    pending: Vec<i64>,
+}
+
+impl FrameAssembler {
+    fn new() -> FrameAssembler {
+        FrameAssembler { pending: vec![] }
+    }
+
+    fn feed(&mut self, output: i64) -> Option<Frame> {
+        self.pending.push(output);
+        if self.pending.len() < 3 {
+            return None;
+        }
+        let frame = match self.pending[..] {
+            [-1, 0, score] => Frame::Score(score),
+            [x, y, tile] => Frame::Draw((x, y), Tile::from(tile)),
+            _ => unreachable!(),
+        };
+        self.pending.clear();
+        Some(frame)
+    }
+
+    /// Reads outputs until a frame is complete, or returns None once the game halts cleanly between frames
+    fn next_frame(&mut self, game: &mut intcode::Cpu) -> Option<Frame> {
+        while let Some(output) = game.run() {
+            if let Some(frame) = self.feed(output) {
+                return Some(frame);
+            }
+        }
+        assert!(
+            self.pending.is_empty(),
+            "Game halted part way through a frame: {:?}",
+            self.pending
+        );
+        None
+    }
+}
 
-#[allow(dead_code)]
 fn print_screen(screen: &Screen, score: i64) {
     let (mut min_x, mut max_x, mut min_y, mut max_y) = (0, 0, 0, 0);
-    for (x, y) in screen.keys() {
+    for (x, y) in screen.tiles.keys() {
         min_x = min(min_x, *x);
         min_y = min(min_y, *y);
         max_x = max(max_x, *x);
@@ -75,7 +193,7 @@ fn print_screen(screen: &Screen, score: i64) {
 struct BallPredictionEngine {
     ball_momentum: Input,
     prev_ball_pos: (i64, i64),
-    prev_screen: Screen,
+    prev_block_count: usize,
 }
 
 impl BallPredictionEngine {
@@ -83,37 +201,18 @@ impl BallPredictionEngine {
         BallPredictionEngine {
             ball_momentum: Input::RIGHT,
             prev_ball_pos: (0, 0),
-            prev_screen: Screen::new(),
+            prev_block_count: 0,
         }
     }
 
     fn move_paddle(&mut self, game: &mut intcode::Cpu, screen: &Screen) {
         // Do you like state machines and if/else branches? Welcome!
-        let ball_pos = screen
-            .iter()
-            .filter(|e| *e.1 == Tile::BALL)
-            .map(|e| e.0)
-            .next();
-        let paddle_pos = screen
-            .iter()
-            .filter(|e| *e.1 == Tile::HORIPAD)
-            .map(|e| e.0)
-            .next();
-        let blocks = screen
-            .iter()
-            .filter(|e| *e.1 == Tile::BLOCK)
-            .map(|e| *e.0)
-            .collect::<HashSet<(i64, i64)>>();
-        let prev_blocks = self
-            .prev_screen
-            .iter()
-            .filter(|e| *e.1 == Tile::BLOCK)
-            .map(|e| *e.0)
-            .collect::<HashSet<(i64, i64)>>();
+        let ball_pos = screen.ball_pos();
+        let paddle_pos = screen.paddle_pos();
         // Block was broken? Check if we need to reverse ball momentum
-        if blocks.len() < prev_blocks.len() {
+        if screen.block_count() < self.prev_block_count {
             let ball_pos = ball_pos.unwrap();
-            let block_pos = prev_blocks.difference(&blocks).next().unwrap();
+            let block_pos = screen.last_broken_block().unwrap();
             self.ball_momentum = if ball_pos.0 < block_pos.0 {
                 Input::LEFT
             } else if ball_pos.0 > block_pos.0 {
@@ -122,10 +221,10 @@ impl BallPredictionEngine {
                 self.ball_momentum
             };
             // Recalculate inputs for unexpected bounce
-            game.inputs.clear();
+            game.clear_inputs();
         }
         // Game is awaiting input and we have needed positional info
-        if game.inputs.len() == 0 && ball_pos.is_some() && paddle_pos.is_some() {
+        if !game.has_pending_input() && ball_pos.is_some() && paddle_pos.is_some() {
             let ball_pos = ball_pos.unwrap();
             let paddle_pos = paddle_pos.unwrap();
             // Update ball momentum based on previous position
@@ -166,46 +265,140 @@ impl BallPredictionEngine {
             } else {
                 self.ball_momentum
             };
-            self.prev_ball_pos = *ball_pos;
-            self.prev_screen = screen.clone();
+            self.prev_ball_pos = ball_pos;
+            self.prev_block_count = screen.block_count();
             game.push(&vec![input.to_i64()]);
         }
     }
 }
 
-fn main() {
-    let rom = intcode::parse_rom("input");
-    println!("13-1:");
-    let mut game = intcode::Cpu::new(rom.to_vec());
-    let mut screen = Screen::new();
-    loop {
-        let (x, y, tile) = (game.run(), game.run(), game.run());
-        match (x, y, tile) {
-            (Some(x), Some(y), Some(tile)) => screen.insert((x, y), Tile::from(tile)),
-            _ => break,
-        };
-    }
-    println!("{}", screen.values().filter(|t| **t == Tile::BLOCK).count());
-    println!("13-2:");
+/// Plays the game to completion, returning the final score and the blocks remaining at every score update. With
+/// `render` set the screen is redrawn in the terminal after every frame.
+fn play(rom: &[i64], render: bool) -> (i64, Vec<usize>) {
     let mut free_rom = rom.to_vec();
     free_rom[0] = 2;
     let mut game = intcode::Cpu::new(free_rom);
     game.push(&vec![Input::RIGHT.to_i64()]);
     let mut screen = Screen::new();
     let mut score = 0;
+    let mut blocks_remaining = vec![];
     let mut aimbot = BallPredictionEngine::new();
-    loop {
-        let (x, y, tile) = (game.run(), game.run(), game.run());
-        match (x, y, tile) {
-            (Some(-1), Some(0), Some(new_score)) => {
+    let mut frames = FrameAssembler::new();
+    while let Some(frame) = frames.next_frame(&mut game) {
+        match frame {
+            Frame::Score(new_score) => {
                 score = new_score;
+                blocks_remaining.push(screen.block_count());
             }
-            (Some(x), Some(y), Some(tile)) => {
-                screen.insert((x, y), Tile::from(tile));
-            }
-            _ => break,
+            Frame::Draw(pos, tile) => screen.insert(pos, tile),
         };
+        if render {
+            // Clear the terminal and move the cursor back to the top left
+            print!("\x1B[2J\x1B[H");
+            print_screen(&screen, score);
+        }
         aimbot.move_paddle(&mut game, &screen);
     }
-    println!("{}", score);
+    (score, blocks_remaining)
+}
+
+fn play_to_win(rom: &[i64], render: bool) -> i64 {
+    play(rom, render).0
+}
+
+fn main() {
+    let rom = intcode::parse_rom("input");
+    println!("13-1:");
+    let mut game = intcode::Cpu::new(rom.to_vec());
+    let mut screen = Screen::new();
+    let mut frames = FrameAssembler::new();
+    while let Some(frame) = frames.next_frame(&mut game) {
+        if let Frame::Draw(pos, tile) = frame {
+            screen.insert(pos, tile);
+        }
+    }
+    println!("{}", screen.block_count());
+    println!("13-2:");
+    println!("{}", play_to_win(&rom, false));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn screen_tracks_tiles_through_overwrites() {
+        let mut screen = Screen::new();
+        screen.insert((1, 1), Tile::BLOCK);
+        screen.insert((2, 1), Tile::BLOCK);
+        screen.insert((3, 4), Tile::BALL);
+        screen.insert((3, 6), Tile::HORIPAD);
+        assert_eq!(screen.block_count(), 2);
+        assert_eq!(screen.ball_pos(), Some((3, 4)));
+        assert_eq!(screen.paddle_pos(), Some((3, 6)));
+
+        // The ball moves by erasing its old tile and drawing the new one
+        screen.insert((3, 4), Tile::EMPTY);
+        assert_eq!(screen.ball_pos(), None);
+        screen.insert((4, 3), Tile::BALL);
+        assert_eq!(screen.ball_pos(), Some((4, 3)));
+
+        screen.insert((1, 1), Tile::EMPTY);
+        assert_eq!(screen.block_count(), 1);
+        assert_eq!(screen.last_broken_block(), Some((1, 1)));
+
+        // Redrawing a block in place doesn't count it twice
+        screen.insert((2, 1), Tile::BLOCK);
+        assert_eq!(screen.block_count(), 1);
+        assert_eq!(screen.last_broken_block(), Some((1, 1)));
+        assert_eq!(screen.paddle_pos(), Some((3, 6)));
+    }
+
+    #[test]
+    fn blocks_only_ever_decrease_until_won() {
+        let (score, blocks_remaining) = play(&intcode::parse_rom("input"), false);
+        assert_eq!(score, 22225);
+        assert!(blocks_remaining.windows(2).all(|pair| pair[1] <= pair[0]));
+        assert_eq!(blocks_remaining.last(), Some(&0));
+    }
+
+    #[test]
+    fn tiles_from_outputs() {
+        assert_eq!(Tile::try_from(0).unwrap(), Tile::EMPTY);
+        assert_eq!(Tile::try_from(1).unwrap(), Tile::WALL);
+        assert_eq!(Tile::try_from(2).unwrap(), Tile::BLOCK);
+        assert_eq!(Tile::try_from(3).unwrap(), Tile::HORIPAD);
+        assert_eq!(Tile::try_from(4).unwrap(), Tile::BALL);
+        assert!(matches!(Tile::try_from(5), Err(InvalidTile(5))));
+        assert!(matches!(Tile::try_from(-1), Err(InvalidTile(-1))));
+    }
+
+    #[test]
+    fn frames_from_outputs() {
+        let mut game = intcode::Cpu::new(vec![104, 1, 104, 2, 104, 3, 104, -1, 104, 0, 104, 7, 99]);
+        let mut frames = FrameAssembler::new();
+        assert!(matches!(
+            frames.next_frame(&mut game),
+            Some(Frame::Draw((1, 2), Tile::HORIPAD))
+        ));
+        assert!(matches!(
+            frames.next_frame(&mut game),
+            Some(Frame::Score(7))
+        ));
+        assert!(frames.next_frame(&mut game).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Game halted part way through a frame: [4]")]
+    fn halt_mid_frame_is_detected() {
+        let mut game = intcode::Cpu::new(vec![104, 1, 104, 2, 104, 3, 104, 4, 99]);
+        let mut frames = FrameAssembler::new();
+        assert!(frames.next_frame(&mut game).is_some());
+        frames.next_frame(&mut game);
+    }
+
+    #[test]
+    fn play_to_win_without_rendering() {
+        assert_eq!(play_to_win(&intcode::parse_rom("input"), false), 22225);
+    }
 }
@@ -20,8 +20,48 @@ fn parse_ingredient(ingredient: &str) -> Ingredient {
     (quantity, material)
 }
 
-fn parse_input(filename: &str) -> RecipeBook {
-    let input = std::fs::read_to_string(filename).unwrap();
+#[derive(Debug)]
+enum RecipeError {
+    UndefinedMaterial { material: String, needed_by: String },
+}
+
+impl std::fmt::Display for RecipeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RecipeError::UndefinedMaterial {
+                material,
+                needed_by,
+            } => write!(
+                f,
+                "No recipe produces {}, which is needed by {}",
+                material, needed_by
+            ),
+        }
+    }
+}
+
+/// Makes sure every ingredient other than ORE can actually be produced
+fn validate(recipes: &RecipeBook) -> Result<(), RecipeError> {
+    let mut outputs = recipes.keys().collect::<Vec<_>>();
+    outputs.sort();
+    for output in outputs {
+        for (_, material) in &recipes[output].inputs {
+            if material != "ORE" && !recipes.contains_key(material) {
+                return Err(RecipeError::UndefinedMaterial {
+                    material: material.clone(),
+                    needed_by: output.clone(),
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+fn parse_input(filename: &str) -> Result<RecipeBook, RecipeError> {
+    parse_recipes(&std::fs::read_to_string(filename).unwrap())
+}
+
+fn parse_recipes(input: &str) -> Result<RecipeBook, RecipeError> {
     let mut recipes = RecipeBook::new();
     for line in input.lines() {
         let sides = line.split("=>").collect::<Vec<&str>>();
@@ -33,7 +73,8 @@ fn parse_input(filename: &str) -> RecipeBook {
         let result = parse_ingredient(sides[1].trim());
         recipes.insert(result.1.clone(), Recipe::new(ingredients, result));
     }
-    recipes
+    validate(&recipes)?;
+    Ok(recipes)
 }
 
 fn ore_cost(
@@ -66,11 +107,42 @@ fn ore_cost(
     ore
 }
 
-fn search<T>(limit: usize, function: T) -> usize
+/// Ore needed to make a single batch of each material from an empty inventory, worked out at most once per material
+struct BatchCosts<'a> {
+    recipes: &'a RecipeBook,
+    cache: HashMap<String, usize>,
+}
+
+impl<'a> BatchCosts<'a> {
+    fn new(recipes: &'a RecipeBook) -> BatchCosts<'a> {
+        BatchCosts {
+            recipes,
+            cache: HashMap::new(),
+        }
+    }
+
+    /// Since leftovers can only ever be shared between batches, `n * ore_per_batch` is an upper bound on the ore
+    /// needed for `n` batches. The recipes can't change while borrowed, so a cached value never goes stale.
+    fn ore_per_batch(&mut self, material: &str) -> usize {
+        if material == "ORE" {
+            return 1;
+        }
+        if let Some(ore) = self.cache.get(material) {
+            return *ore;
+        }
+        let batch = (self.recipes[material].output.0, String::from(material));
+        let ore = ore_cost(&batch, &mut HashMap::new(), self.recipes);
+        self.cache.insert(String::from(material), ore);
+        ore
+    }
+}
+
+/// Finds the largest input whose result doesn't exceed `limit`, given that `function(lower) <= limit`
+fn search<T>(limit: usize, lower: usize, function: T) -> usize
 where
     T: Fn(usize) -> usize,
 {
-    let mut higher = 1;
+    let mut higher = lower + 1;
     while function(higher) < limit {
         higher *= 2;
     }
@@ -91,19 +163,156 @@ where
             n
         }
     };
-    recursive_search(1, higher, limit, function)
+    recursive_search(lower, higher, limit, function)
+}
+
+fn ore_for_fuel(fuel: usize, recipes: &RecipeBook) -> usize {
+    ore_cost(&(fuel, String::from("FUEL")), &mut HashMap::new(), recipes)
+}
+
+fn max_fuel(ore_limit: usize, recipes: &RecipeBook) -> usize {
+    // Making every fuel from scratch is the worst case, so it's a safe place to start searching from
+    let lower = ore_limit / BatchCosts::new(recipes).ore_per_batch("FUEL");
+    search(ore_limit, lower, |fuel| ore_for_fuel(fuel, recipes))
+}
+
+/// `(fuel, ore)` for each of `fuels`, for checking how close to linear the ore cost is
+#[allow(dead_code)]
+fn cost_curve(recipes: &RecipeBook, fuels: &[usize]) -> Vec<(usize, usize)> {
+    fuels
+        .iter()
+        .map(|fuel| (*fuel, ore_for_fuel(*fuel, recipes)))
+        .collect()
+}
+
+/// Ore needed for a single fuel, and the most fuel a trillion ore can make
+fn solve(recipes: &RecipeBook) -> (usize, usize) {
+    (
+        ore_for_fuel(1, recipes),
+        max_fuel(1_000_000_000_000, recipes),
+    )
 }
 
 fn main() {
-    let recipes = parse_input("input");
-    println!(
-        "14-1:\n{}",
-        ore_cost(&(1, String::from("FUEL")), &mut HashMap::new(), &recipes)
-    );
-    println!(
-        "14-2:\n{}",
-        search(1_000_000_000_000, |fuel| {
-            ore_cost(&(fuel, String::from("FUEL")), &mut HashMap::new(), &recipes)
-        })
-    );
+    let recipes = parse_input("input").unwrap_or_else(|e| panic!("Invalid recipes: {}", e));
+    let (ore, fuel) = solve(&recipes);
+    println!("14-1:\n{}\n14-2:\n{}", ore, fuel);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "157 ORE => 5 NZVS
+165 ORE => 6 DCFZ
+44 XJWVT, 5 KHKGT, 1 QDVJ, 29 NZVS, 9 GPVTF, 48 HKGWZ => 1 FUEL
+12 HKGWZ, 1 GPVTF, 8 PSHF => 9 QDVJ
+179 ORE => 7 PSHF
+177 ORE => 5 HKGWZ
+7 DCFZ, 7 PSHF => 2 XJWVT
+165 ORE => 2 GPVTF
+3 DCFZ, 7 NZVS, 5 HKGWZ, 10 PSHF => 8 KHKGT
+";
+
+    #[test]
+    fn seeded_search_matches_unseeded() {
+        let recipes = parse_recipes(EXAMPLE).unwrap();
+        let limit = 1_000_000_000_000;
+        let unseeded = search(limit, 1, |fuel| ore_for_fuel(fuel, &recipes));
+        assert_eq!(unseeded, 82892753);
+        assert_eq!(max_fuel(limit, &recipes), unseeded);
+        assert_eq!(solve(&recipes), (13312, 82892753));
+    }
+
+    #[test]
+    fn batch_costs_are_cached() {
+        let recipes = parse_recipes(EXAMPLE).unwrap();
+        let mut costs = BatchCosts::new(&recipes);
+        assert_eq!(costs.ore_per_batch("ORE"), 1);
+        assert_eq!(costs.ore_per_batch("FUEL"), 13312);
+        assert_eq!(costs.ore_per_batch("NZVS"), 157);
+        assert_eq!(costs.cache.len(), 2);
+        assert_eq!(costs.ore_per_batch("FUEL"), 13312);
+        assert_eq!(costs.cache.len(), 2);
+    }
+
+    #[test]
+    fn undefined_materials_are_rejected() {
+        let broken = EXAMPLE.replace("179 ORE => 7 PSHF\n", "");
+        match parse_recipes(&broken) {
+            Err(RecipeError::UndefinedMaterial {
+                material,
+                needed_by,
+            }) => {
+                // Outputs are checked in name order, so KHKGT is the first to need PSHF
+                assert_eq!(material, "PSHF");
+                assert_eq!(needed_by, "KHKGT");
+            }
+            Ok(_) => panic!("Recipes without PSHF were accepted"),
+        }
+        assert!(parse_recipes("7 A => 1 FUEL\n").is_err());
+        assert!(parse_recipes("7 ORE => 1 FUEL\n").is_ok());
+    }
+
+    #[test]
+    fn solve_published_examples() {
+        let examples = [
+            (
+                "2 VPVL, 7 FWMGM, 2 CXFTF, 11 MNCFX => 1 STKFG
+17 NVRVD, 3 JNWZP => 8 VPVL
+53 STKFG, 6 MNCFX, 46 VJHF, 81 HVMC, 68 CXFTF, 25 GNMV => 1 FUEL
+22 VJHF, 37 MNCFX => 5 FWMGM
+139 ORE => 4 NVRVD
+144 ORE => 7 JNWZP
+5 MNCFX, 7 RFSQX, 2 FWMGM, 2 VPVL, 19 CXFTF => 3 HVMC
+5 VJHF, 7 MNCFX, 9 VPVL, 37 CXFTF => 6 GNMV
+145 ORE => 6 MNCFX
+1 NVRVD => 8 CXFTF
+1 VJHF, 6 MNCFX => 4 RFSQX
+176 ORE => 6 VJHF
+",
+                (180697, 5586022),
+            ),
+            (
+                "171 ORE => 8 CNZTR
+7 ZLQW, 3 BMBT, 9 XCVML, 26 XMNCP, 1 WPTQ, 2 MZWV, 1 RJRHP => 4 PLWSL
+114 ORE => 4 BHXH
+14 VRPVC => 6 BMBT
+6 BHXH, 18 KTJDG, 12 WPTQ, 7 PLWSL, 31 FHTLT, 37 ZDVW => 1 FUEL
+6 WPTQ, 2 BMBT, 8 ZLQW, 18 KTJDG, 1 XMNCP, 6 MZWV, 1 RJRHP => 6 FHTLT
+15 XDBXC, 2 LTCX, 1 VRPVC => 6 ZLQW
+13 WPTQ, 10 LTCX, 3 RJRHP, 14 XMNCP, 2 MZWV, 1 ZLQW => 1 ZDVW
+5 BMBT => 4 WPTQ
+189 ORE => 9 KTJDG
+1 MZWV, 17 XDBXC, 3 XCVML => 2 XMNCP
+12 VRPVC, 27 CNZTR => 2 XDBXC
+15 KTJDG, 12 BHXH => 5 XCVML
+3 BHXH, 2 VRPVC => 7 MZWV
+121 ORE => 7 VRPVC
+7 XCVML => 6 RJRHP
+5 BHXH, 4 VRPVC => 5 LTCX
+",
+                (2210736, 460664),
+            ),
+        ];
+        for (src, answers) in examples.iter() {
+            assert_eq!(solve(&parse_recipes(src).unwrap()), *answers);
+        }
+    }
+
+    #[test]
+    fn cost_curve_never_decreases() {
+        let recipes = parse_recipes(EXAMPLE).unwrap();
+        let fuels = (1..=20)
+            .chain(vec![100, 1_000, 82_892_753, 82_892_754])
+            .collect::<Vec<_>>();
+        let curve = cost_curve(&recipes, &fuels);
+        assert_eq!(curve.len(), fuels.len());
+        assert_eq!(curve[0], (1, 13312));
+        assert!(curve.windows(2).all(|pair| pair[0].1 <= pair[1].1));
+        // Leftovers get reused, so a fuel never costs more than the first one did
+        assert!(curve.iter().all(|(fuel, ore)| *ore <= fuel * 13312));
+        let (last, over) = (curve[curve.len() - 2], curve[curve.len() - 1]);
+        assert!(last.1 <= 1_000_000_000_000 && over.1 > 1_000_000_000_000);
+    }
 }
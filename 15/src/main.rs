@@ -1,9 +1,11 @@
+extern crate day;
 extern crate intcode;
 
+use day::direction::{Direction, Position};
 use std::cmp::{max, min};
+use std::collections::hash_map::Entry;
 use std::collections::{BTreeMap, HashMap, VecDeque};
-
-type Position = (i64, i64);
+use std::convert::TryFrom;
 
 #[derive(Copy, Clone, Debug, PartialEq)]
 enum TileKind {
@@ -12,17 +14,28 @@ enum TileKind {
     GOAL,
 }
 
-impl TileKind {
-    fn from(i: i64) -> TileKind {
+#[derive(Debug)]
+struct InvalidTile(i64);
+
+impl TryFrom<i64> for TileKind {
+    type Error = InvalidTile;
+
+    fn try_from(i: i64) -> Result<TileKind, InvalidTile> {
         match i {
-            0 => TileKind::WALL,
-            1 => TileKind::EMPTY,
-            2 => TileKind::GOAL,
-            _ => panic!("Invalid tile type"),
+            0 => Ok(TileKind::WALL),
+            1 => Ok(TileKind::EMPTY),
+            2 => Ok(TileKind::GOAL),
+            _ => Err(InvalidTile(i)),
         }
     }
 }
 
+impl TileKind {
+    fn from(i: i64) -> TileKind {
+        TileKind::try_from(i).unwrap_or_else(|InvalidTile(i)| panic!("Invalid tile type {:?}", i))
+    }
+}
+
 #[derive(Copy, Clone)]
 struct Tile {
     kind: TileKind,
@@ -38,22 +51,13 @@ impl Tile {
 
 type Map = HashMap<Position, Tile>;
 
-#[derive(Copy, Clone, Debug, PartialEq)]
-enum Input {
-    UP,
-    DOWN,
-    LEFT,
-    RIGHT,
-}
-
-impl Input {
-    fn to_i64(&self) -> i64 {
-        match self {
-            Input::UP => 1,
-            Input::DOWN => 2,
-            Input::LEFT => 3,
-            Input::RIGHT => 4,
-        }
+/// Movement command the repair droid expects for each direction
+fn movement_command(direction: Direction) -> i64 {
+    match direction {
+        Direction::Up => 1,
+        Direction::Down => 2,
+        Direction::Left => 3,
+        Direction::Right => 4,
     }
 }
 
@@ -85,20 +89,14 @@ impl LocatorRobot {
                 inputs.extend(move_to(map, from, Some(pos)));
             }
             if let Some(to) = to {
-                if to.0 > pos.0 {
-                    inputs.push(Input::RIGHT.to_i64());
-                } else if to.0 < pos.0 {
-                    inputs.push(Input::LEFT.to_i64());
-                } else if to.1 > pos.1 {
-                    inputs.push(Input::DOWN.to_i64());
-                } else if to.1 < pos.1 {
-                    inputs.push(Input::UP.to_i64());
+                if let Some(direction) = Direction::ALL.iter().find(|d| d.step(pos) == to) {
+                    inputs.push(movement_command(*direction));
                 }
             }
             inputs
         }
         robot.push(&move_to(&self.map, pos, None));
-        while robot.inputs.len() > 0 {
+        while robot.has_pending_input() {
             robot.run();
         }
     }
@@ -110,13 +108,8 @@ impl LocatorRobot {
     fn solve_position(&mut self, pos: Position) {
         let current_tile = self.map.get(&pos).unwrap();
         let current_tile_cost = current_tile.cost.expect("Cannot solve for a wall");
-        for direction in [Input::UP, Input::DOWN, Input::LEFT, Input::RIGHT].iter() {
-            let next_pos = match direction {
-                Input::UP => (pos.0, pos.1 - 1),
-                Input::DOWN => (pos.0, pos.1 + 1),
-                Input::LEFT => (pos.0 - 1, pos.1),
-                Input::RIGHT => (pos.0 + 1, pos.1),
-            };
+        for direction in Direction::ALL.iter() {
+            let next_pos = direction.step(pos);
             let next_tile = if let Some(mut tile) = self.map.get_mut(&next_pos).cloned() {
                 if tile.kind == TileKind::WALL {
                     continue;
@@ -130,9 +123,10 @@ impl LocatorRobot {
             } else {
                 // Could massively speed this up if we cached CPU states for each location
                 // But it's still only a couple seconds run time, thanks native compilers
-                let mut robot = self.cpu.clone();
+                let mut robot = self.cpu.fork();
                 self.move_from_origin_to(pos, &mut robot);
-                let kind = TileKind::from(robot.run_with(&vec![direction.to_i64()]).unwrap());
+                let kind =
+                    TileKind::from(robot.run_with(&vec![movement_command(*direction)]).unwrap());
                 let cost = match kind {
                     TileKind::WALL => None,
                     _ => Some(current_tile_cost + 1),
@@ -149,6 +143,37 @@ impl LocatorRobot {
         }
     }
 
+    /// Breadth-first search over the already explored map, no CPU required
+    fn distances_from(&self, start: Position) -> HashMap<Position, i64> {
+        let mut distances = HashMap::new();
+        let mut queue = VecDeque::new();
+        distances.insert(start, 0);
+        queue.push_back(start);
+        while let Some(pos) = queue.pop_front() {
+            let cost = distances[&pos];
+            for direction in Direction::ALL.iter() {
+                let next_pos = direction.step(pos);
+                match self.map.get(&next_pos) {
+                    Some(tile) if tile.kind != TileKind::WALL => {}
+                    _ => continue,
+                }
+                if let Entry::Vacant(distance) = distances.entry(next_pos) {
+                    distance.insert(cost + 1);
+                    queue.push_back(next_pos);
+                }
+            }
+        }
+        distances
+    }
+
+    /// Where the oxygen system is, once it's been explored. There's only ever one on the map.
+    fn goal_position(&self) -> Option<Position> {
+        self.map
+            .iter()
+            .find(|(_, tile)| tile.kind == TileKind::GOAL)
+            .map(|(pos, _)| *pos)
+    }
+
     fn add_unsolved(&mut self, cost: i64, pos: Position) {
         self.unsolved
             .entry(cost)
@@ -207,22 +232,80 @@ fn main() {
     println!("15-1:");
     let mut robot = LocatorRobot::new(intcode::Cpu::new(rom.to_vec()));
     robot.solve();
-    let (pos, goal) = robot
-        .map
-        .iter()
-        .filter(|(_, t)| t.kind == TileKind::GOAL)
-        .next()
-        .unwrap();
-    println!("{}", goal.cost.unwrap());
+    let goal = robot.goal_position().expect("Could not find oxygen system");
+    println!("{}", robot.map[&goal].cost.unwrap());
     println!("15-2:");
-    let mut cpu = intcode::Cpu::new(rom.to_vec());
-    // Re-using solved completion state to a set base CPU state where the origin is the goal
-    robot.move_from_origin_to(*pos, &mut cpu);
-    let mut robot = LocatorRobot::new(cpu);
-    robot.solve();
-    let time = robot
-        .map
-        .values()
-        .fold(0, |acc, tile| max(acc, tile.cost.unwrap_or(0)));
+    let time = robot.distances_from(goal).values().cloned().max().unwrap();
     println!("{}", time);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Robot that has already explored `layout`, where '#' is a wall, '.' is open and 'O' is the oxygen system
+    fn explored(layout: &str) -> LocatorRobot {
+        let mut robot = LocatorRobot::new(intcode::Cpu::new(vec![99]));
+        robot.map.clear();
+        for (y, line) in layout.lines().enumerate() {
+            for (x, c) in line.chars().enumerate() {
+                let kind = match c {
+                    '#' => TileKind::WALL,
+                    '.' => TileKind::EMPTY,
+                    'O' => TileKind::GOAL,
+                    _ => continue,
+                };
+                robot
+                    .map
+                    .insert((x as i64, y as i64), Tile::new(kind, None, None));
+            }
+        }
+        robot
+    }
+
+    #[test]
+    fn distances_from_goal_on_example() {
+        let robot = explored(
+            " ##
+#..##
+#.#..#
+#.O.#
+ ###",
+        );
+        let goal = robot.goal_position().unwrap();
+        assert_eq!(goal, (2, 3));
+        let distances = robot.distances_from(goal);
+        assert_eq!(distances.len(), 8);
+        assert_eq!(distances[&(1, 1)], 3);
+        assert_eq!(distances[&(4, 2)], 3);
+        assert_eq!(distances[&(2, 1)], 4);
+        assert_eq!(distances.values().max(), Some(&4));
+    }
+
+    #[test]
+    fn tile_kinds_from_status_codes() {
+        assert_eq!(TileKind::try_from(0).unwrap(), TileKind::WALL);
+        assert_eq!(TileKind::try_from(1).unwrap(), TileKind::EMPTY);
+        assert_eq!(TileKind::try_from(2).unwrap(), TileKind::GOAL);
+        assert!(matches!(TileKind::try_from(3), Err(InvalidTile(3))));
+    }
+
+    #[test]
+    fn goal_position_finds_the_oxygen_system() {
+        assert_eq!(explored("#.#\n...\n#.#").goal_position(), None);
+        assert_eq!(explored("#.#\n..O\n#.#").goal_position(), Some((2, 1)));
+
+        let mut robot = LocatorRobot::new(intcode::Cpu::new(intcode::parse_rom("input")));
+        assert_eq!(robot.goal_position(), None);
+        robot.solve();
+        let goal = robot.goal_position().unwrap();
+        assert_eq!(robot.map[&goal].kind, TileKind::GOAL);
+        assert_eq!(robot.map[&goal].cost, Some(404));
+        let goals = robot
+            .map
+            .values()
+            .filter(|tile| tile.kind == TileKind::GOAL)
+            .count();
+        assert_eq!(goals, 1);
+    }
+}
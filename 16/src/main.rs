@@ -7,6 +7,65 @@ fn parse_input(filename: &str) -> Vec<i64> {
         .collect()
 }
 
+#[derive(Debug)]
+enum ConvertError {
+    InvalidDigit { index: usize, value: i64 },
+    TooShort { len: usize, needed: usize },
+}
+
+impl std::fmt::Display for ConvertError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConvertError::InvalidDigit { index, value } => {
+                write!(
+                    f,
+                    "Value {} at index {} is not a single digit",
+                    value, index
+                )
+            }
+            ConvertError::TooShort { len, needed } => {
+                write!(f, "Need {} digits but only have {}", needed, len)
+            }
+        }
+    }
+}
+
+fn digits_to_string(digits: &[i64]) -> Result<String, ConvertError> {
+    digits
+        .iter()
+        .enumerate()
+        .map(|(index, value)| match *value {
+            0..=9 => Ok(std::char::from_digit(*value as u32, 10).unwrap()),
+            _ => Err(ConvertError::InvalidDigit {
+                index,
+                value: *value,
+            }),
+        })
+        .collect()
+}
+
+const OFFSET_DIGITS: usize = 7;
+
+/// The message offset is given by the first seven digits of the signal
+fn message_offset(digits: &[i64]) -> Result<usize, ConvertError> {
+    if digits.len() < OFFSET_DIGITS {
+        return Err(ConvertError::TooShort {
+            len: digits.len(),
+            needed: OFFSET_DIGITS,
+        });
+    }
+    digits[..OFFSET_DIGITS]
+        .iter()
+        .enumerate()
+        .try_fold(0, |offset, (index, value)| match *value {
+            0..=9 => Ok(offset * 10 + *value as usize),
+            _ => Err(ConvertError::InvalidDigit {
+                index,
+                value: *value,
+            }),
+        })
+}
+
 fn cycle_phase(phase: &Vec<i64>, iterations: usize, offset: usize) -> Vec<i64> {
     let mut phase = phase.to_vec();
     let len = phase.len();
@@ -40,38 +99,182 @@ fn cycle_phase(phase: &Vec<i64>, iterations: usize, offset: usize) -> Vec<i64> {
     phase
 }
 
+/// Resumable FFT. Digits before `offset` never feed into the ones after it, so running 50 phases and then another 50
+/// gives the same result as running 100 in one go.
+struct FftState {
+    phase: Vec<i64>,
+    offset: usize,
+}
+
+impl FftState {
+    fn new(phase: &[i64], offset: usize) -> FftState {
+        FftState {
+            phase: phase.to_vec(),
+            offset,
+        }
+    }
+
+    fn run(&mut self, iterations: usize) {
+        self.phase = cycle_phase(&self.phase, iterations, self.offset);
+    }
+
+    /// Only digits from `offset` onwards are meaningful
+    fn digits(&self) -> &[i64] {
+        &self.phase[self.offset..]
+    }
+}
+
 fn main() {
     let phase = parse_input("input");
-    println!(
-        "16-1:\n{}",
-        cycle_phase(&phase, 100, 0)
-            .iter()
-            .take(8)
-            .flat_map(|i| std::iter::once(std::char::from_digit(*i as u32, 10).unwrap()))
-            .collect::<String>()
-    );
+    let to_string = |digits: &[i64]| {
+        digits_to_string(digits).unwrap_or_else(|e| panic!("Invalid output: {}", e))
+    };
+    println!("16-1:\n{}", to_string(&cycle_phase(&phase, 100, 0)[..8]));
 
-    // I think this is more readable than a multiplicative fold. Don't judge, thanks.
-    let offset: usize = phase
-        .iter()
-        .take(7)
-        .flat_map(|i| std::iter::once(std::char::from_digit(*i as u32, 10).unwrap()))
-        .collect::<String>()
-        .parse()
-        .unwrap();
+    let offset = message_offset(&phase).unwrap_or_else(|e| panic!("Invalid offset: {}", e));
     let full_phase = phase
         .iter()
         .cycle()
         .take(phase.len() * 10_000)
         .cloned()
         .collect::<Vec<i64>>();
-    println!(
-        "16-2\n{}",
-        cycle_phase(&full_phase, 100, offset)
+    let mut fft = FftState::new(&full_phase, offset);
+    fft.run(100);
+    println!("16-2\n{}", to_string(&fft.digits()[..8]));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Straightforward full convolution of every digit, as a reference for checking `cycle_phase`
+    fn cycle_phase_naive(phase: &[i64], iterations: usize) -> Vec<i64> {
+        let mut phase = phase.to_vec();
+        for _ in 0..iterations {
+            phase = (0..phase.len())
+                .map(|i| {
+                    let base = [0, 1, 0, -1]
+                        .iter()
+                        .flat_map(|n| std::iter::repeat_n(n, i + 1))
+                        .cycle()
+                        .skip(1);
+                    phase
+                        .iter()
+                        .zip(base)
+                        .map(|(a, b)| a * b)
+                        .sum::<i64>()
+                        .abs()
+                        % 10
+                })
+                .collect();
+        }
+        phase
+    }
+
+    /// Small xorshift generator so the test inputs vary but stay reproducible
+    fn random_digits(seed: &mut u64, len: usize) -> Vec<i64> {
+        (0..len)
+            .map(|_| {
+                *seed ^= *seed << 13;
+                *seed ^= *seed >> 7;
+                *seed ^= *seed << 17;
+                (*seed % 10) as i64
+            })
+            .collect()
+    }
+
+    #[test]
+    fn cycle_phase_matches_naive() {
+        let mut seed = 0x2019_1216;
+        for len in 1..40 {
+            let phase = random_digits(&mut seed, len);
+            for iterations in 0..5 {
+                assert_eq!(
+                    cycle_phase(&phase, iterations, 0),
+                    cycle_phase_naive(&phase, iterations),
+                    "len {} iterations {} input {:?}",
+                    len,
+                    iterations,
+                    phase
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn naive_matches_published_example() {
+        let phase = vec![1, 2, 3, 4, 5, 6, 7, 8];
+        assert_eq!(cycle_phase_naive(&phase, 4), vec![0, 1, 0, 2, 9, 4, 9, 8]);
+    }
+
+    #[test]
+    fn resumed_fft_matches_single_run() {
+        let mut seed = 0x2019_1609;
+        let phase = random_digits(&mut seed, 64);
+        let mut fft = FftState::new(&phase, 0);
+        fft.run(3);
+        fft.run(4);
+        assert_eq!(fft.digits(), &cycle_phase(&phase, 7, 0)[..]);
+
+        let signal = [
+            0, 3, 0, 3, 6, 7, 3, 2, 5, 7, 7, 2, 1, 2, 9, 4, 4, 0, 6, 3, 4, 9, 1, 5, 6, 5, 4, 7, 4,
+            6, 6, 4,
+        ];
+        let full_phase = signal
             .iter()
-            .skip(offset)
-            .take(8)
-            .flat_map(|i| std::iter::once(std::char::from_digit(*i as u32, 10).unwrap()))
-            .collect::<String>()
-    );
+            .cycle()
+            .take(signal.len() * 10_000)
+            .cloned()
+            .collect::<Vec<i64>>();
+        let offset = message_offset(&signal).unwrap();
+        let mut fft = FftState::new(&full_phase, offset);
+        fft.run(50);
+        fft.run(50);
+        assert_eq!(
+            fft.digits(),
+            &cycle_phase(&full_phase, 100, offset)[offset..]
+        );
+        assert_eq!(digits_to_string(&fft.digits()[..8]).unwrap(), "84462026");
+    }
+
+    #[test]
+    fn digits_to_string_rejects_stray_values() {
+        assert_eq!(digits_to_string(&[0, 1, 2, 9]).unwrap(), "0129");
+        assert!(matches!(
+            digits_to_string(&[4, 12, 3]),
+            Err(ConvertError::InvalidDigit {
+                index: 1,
+                value: 12
+            })
+        ));
+        assert!(matches!(
+            digits_to_string(&[-1]),
+            Err(ConvertError::InvalidDigit {
+                index: 0,
+                value: -1
+            })
+        ));
+    }
+
+    #[test]
+    fn message_offset_matches_string_parse() {
+        let phase = parse_input("input");
+        let parsed: usize = digits_to_string(&phase[..OFFSET_DIGITS])
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert_eq!(message_offset(&phase).unwrap(), parsed);
+        assert_eq!(message_offset(&[0, 0, 0, 0, 0, 4, 2, 9]).unwrap(), 42);
+        assert!(matches!(
+            message_offset(&[1, 2, 3]),
+            Err(ConvertError::TooShort { len: 3, needed: 7 })
+        ));
+        assert!(matches!(
+            message_offset(&[1, 2, 3, 4, 5, 10, 7]),
+            Err(ConvertError::InvalidDigit {
+                index: 5,
+                value: 10
+            })
+        ));
+    }
 }
@@ -12,38 +12,58 @@ enum TileKind {
 type Map = HashMap<Position, TileKind>;
 
 fn scan_map(rom: &Vec<i64>) -> Map {
-    let mut cpu = intcode::Cpu::new(rom.to_vec());
-    let mut pos: Position = (0, 0);
+    let (view, _, _) = scan_map_string(rom);
+    parse_scaffold(&view).0
+}
+
+/// Builds the map from a camera view, along with the robot's position and the glyph it's facing with
+fn parse_scaffold(view: &str) -> (Map, (Position, char)) {
     let mut map = Map::new();
-    loop {
-        let mut newline = false;
-        match cpu.run() {
-            Some(c) => match c as u8 as char {
+    let mut robot = None;
+    for (y, line) in view.lines().enumerate() {
+        for (x, c) in line.chars().enumerate() {
+            let pos = (x as i64, y as i64);
+            match c {
                 '#' | '^' | 'v' | '<' | '>' => {
                     map.insert(pos, TileKind::FLOOR);
                 }
                 '.' | 'X' => {
                     map.insert(pos, TileKind::EMPTY);
                 }
-                '\n' => {
-                    newline = true;
-                }
                 _ => {}
-            },
-            None => break,
-        }
-        match newline {
-            true => pos = (0, pos.1 + 1),
-            false => pos.0 += 1,
+            }
+            if let '^' | 'v' | '<' | '>' | 'X' = c {
+                robot = Some((pos, c));
+            }
         }
     }
-    map
+    (map, robot.expect("Could not find robot"))
+}
+
+/// Returns the camera view exactly as rendered, robot glyph included, along with its width and height
+fn scan_map_string(rom: &[i64]) -> (String, usize, usize) {
+    let mut cpu = intcode::Cpu::new(rom.to_vec());
+    let mut view = String::new();
+    while let Some(c) = cpu.run() {
+        view.push(c as u8 as char);
+    }
+    let rows = view.lines().filter(|line| !line.is_empty());
+    let width = rows.clone().map(|line| line.len()).max().unwrap_or(0);
+    let height = rows.count();
+    (view, width, height)
 }
 
 fn intersection_score(map: &Map) -> i64 {
+    scaffold_stats(map).0
+}
+
+/// Alignment parameter sum along with the number of scaffold tiles, which any full path has to cover
+fn scaffold_stats(map: &Map) -> (i64, usize) {
     let mut score = 0;
+    let mut length = 0;
     let tiles = map.iter().filter(|t| *t.1 == TileKind::FLOOR);
     for (pos, _) in tiles {
+        length += 1;
         if let Some(TileKind::EMPTY) | None = map.get(&(pos.0 - 1, pos.1)) {
             continue;
         }
@@ -58,7 +78,7 @@ fn intersection_score(map: &Map) -> i64 {
         }
         score += pos.0 * pos.1;
     }
-    score
+    (score, length)
 }
 
 fn run(rom: &Vec<i64>, path_input: &str, print: bool) -> Option<i64> {
@@ -98,3 +118,51 @@ fn main() {
     .unwrap();
     println!("{}", output);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scanned_view_includes_robot() {
+        let rom = intcode::parse_rom("input");
+        let (view, width, height) = scan_map_string(&rom);
+        assert!(view.contains(|c| "^v<>X".contains(c)));
+        let rows = view
+            .lines()
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<_>>();
+        assert_eq!(rows.len(), height);
+        assert!(rows.iter().all(|row| row.len() == width));
+    }
+
+    #[test]
+    fn parses_example_scaffold() {
+        let view = "\
+..#..........
+..#..........
+#######...###
+#.#...#...#.#
+#############
+..#...#...#..
+..#####...^..
+";
+        let (map, robot) = parse_scaffold(view);
+        assert_eq!(robot, ((10, 6), '^'));
+        assert_eq!(map.len(), 13 * 7);
+        assert_eq!(map[&(10, 6)], TileKind::FLOOR);
+        // Intersections at (2, 2), (2, 4), (6, 4) and (10, 4)
+        assert_eq!(scaffold_stats(&map), (76, 39));
+    }
+
+    #[test]
+    fn scaffold_stats_on_camera_view() {
+        let rom = intcode::parse_rom("input");
+        let (view, _, _) = scan_map_string(&rom);
+        let (map, _) = parse_scaffold(&view);
+        let (score, length) = scaffold_stats(&map);
+        assert_eq!(score, intersection_score(&map));
+        assert_eq!(score, 2788);
+        assert_eq!(length, view.matches(|c| "#^v<>".contains(c)).count());
+    }
+}
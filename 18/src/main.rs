@@ -1,5 +1,6 @@
 use std::cmp::{max, min};
 use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::convert::TryFrom;
 
 type Position = (i64, i64);
 
@@ -12,19 +13,30 @@ enum TileKind {
     DOOR(char),
 }
 
-impl TileKind {
-    fn from(c: char) -> TileKind {
+#[derive(Debug)]
+struct InvalidTile(char);
+
+impl TryFrom<char> for TileKind {
+    type Error = InvalidTile;
+
+    fn try_from(c: char) -> Result<TileKind, InvalidTile> {
         match c {
-            '#' => TileKind::WALL,
-            '.' => TileKind::EMPTY,
-            '@' => TileKind::ORIGIN,
-            'a'..='z' => TileKind::KEY(c),
-            'A'..='Z' => TileKind::DOOR(c.to_ascii_lowercase()),
-            _ => panic!("Invalid tile type"),
+            '#' => Ok(TileKind::WALL),
+            '.' => Ok(TileKind::EMPTY),
+            '@' => Ok(TileKind::ORIGIN),
+            'a'..='z' => Ok(TileKind::KEY(c)),
+            'A'..='Z' => Ok(TileKind::DOOR(c.to_ascii_lowercase())),
+            _ => Err(InvalidTile(c)),
         }
     }
 }
 
+impl TileKind {
+    fn from(c: char) -> TileKind {
+        TileKind::try_from(c).unwrap_or_else(|InvalidTile(c)| panic!("Invalid tile type {:?}", c))
+    }
+}
+
 #[derive(Copy, Clone)]
 struct Tile {
     kind: TileKind,
@@ -81,10 +93,22 @@ where
     }
 }
 
+/// The four tiles a step away from `pos`
+fn neighbours(pos: Position) -> [Position; 4] {
+    [
+        (pos.0, pos.1 - 1),
+        (pos.0, pos.1 + 1),
+        (pos.0 - 1, pos.1),
+        (pos.0 + 1, pos.1),
+    ]
+}
+
 struct MazeSolver {
     origin: Position,
     unsolved: OrderedQueue<Position>,
     map: Map,
+    /// Tiles whose neighbours have been checked so far, to compare how much work each search does
+    expanded: usize,
 }
 
 impl MazeSolver {
@@ -95,6 +119,7 @@ impl MazeSolver {
             origin,
             unsolved: OrderedQueue::new(),
             map: map.clone(),
+            expanded: 0,
         }
     }
 
@@ -103,39 +128,61 @@ impl MazeSolver {
     }
 
     fn solve_position(&mut self, pos: Position, ignore_doors: bool) {
-        let current_tile = self.map.get(&pos).unwrap();
-        let current_tile_cost = current_tile.cost.expect("Cannot solve for a wall");
-        for next_pos in [
-            (pos.0, pos.1 - 1),
-            (pos.0, pos.1 + 1),
-            (pos.0 - 1, pos.1),
-            (pos.0 + 1, pos.1),
-        ]
-        .iter()
-        {
-            let next_tile = match self.map.get_mut(&next_pos).cloned() {
-                Some(mut tile) => {
-                    match tile.kind {
-                        TileKind::WALL => continue,
-                        TileKind::DOOR(_) if ignore_doors == false => continue,
-                        _ => {}
-                    }
-                    if tile.cost.is_none() || tile.cost.unwrap() > current_tile_cost + 1 {
-                        tile.cost = Some(current_tile_cost + 1);
-                        tile.parent = Some(pos);
-                        self.unsolved.add(tile.cost.unwrap(), next_pos);
-                    }
-                    tile
-                }
-                None => panic!("Reached out of map bounds somehow"),
-            };
-            self.map.insert(*next_pos, next_tile);
-        }
+        self.relax(pos, ignore_doors, |_| 0);
         if let Some(next_pos) = self.unsolved.pop() {
             self.solve_position(next_pos.1, ignore_doors);
         }
     }
 
+    /// Offers each open neighbour of `pos` a path through it, queueing any tile whose cost improves at its new cost
+    /// plus `estimate`
+    fn relax<F: Fn(&Position) -> i64>(&mut self, pos: Position, ignore_doors: bool, estimate: F) {
+        let current_tile_cost = self.map[&pos].cost.expect("Cannot solve for a wall");
+        self.expanded += 1;
+        for next_pos in neighbours(pos).iter() {
+            let tile = self
+                .map
+                .get_mut(next_pos)
+                .expect("Reached out of map bounds somehow");
+            match tile.kind {
+                TileKind::WALL => continue,
+                TileKind::DOOR(_) if !ignore_doors => continue,
+                _ => {}
+            }
+            if tile.cost.is_none() || tile.cost.unwrap() > current_tile_cost + 1 {
+                tile.cost = Some(current_tile_cost + 1);
+                tile.parent = Some(pos);
+                self.unsolved
+                    .add(current_tile_cost + 1 + estimate(next_pos), next_pos);
+            }
+        }
+    }
+
+    /// A* search towards `goal` using Manhattan distance as the heuristic, returning the goal's cost. Tile costs stay
+    /// true distances so they match `solve` for every tile settled before the goal. Without a goal this is a plain
+    /// `solve` and returns None.
+    #[allow(dead_code)]
+    fn solve_astar(&mut self, goal: Option<Position>, ignore_doors: bool) -> Option<i64> {
+        let goal = match goal {
+            Some(goal) => goal,
+            None => {
+                self.solve(ignore_doors);
+                return None;
+            }
+        };
+        let heuristic = |pos: &Position| (pos.0 - goal.0).abs() + (pos.1 - goal.1).abs();
+
+        self.unsolved.add(heuristic(&self.origin), &self.origin);
+        while let Some((_, pos)) = self.unsolved.pop() {
+            if pos == goal {
+                break;
+            }
+            self.relax(pos, ignore_doors, heuristic);
+        }
+
+        self.map.get(&goal).and_then(|tile| tile.cost)
+    }
+
     #[allow(dead_code)]
     fn print_map(&self) {
         let (mut min_x, mut max_x, mut min_y, mut max_y) = (0, 0, 0, 0);
@@ -167,6 +214,10 @@ impl MazeSolver {
 
 fn parse_input(filename: &str) -> (Map, Vec<Position>) {
     let input = std::fs::read_to_string(filename).unwrap();
+    parse_str(&input)
+}
+
+fn parse_str(input: &str) -> (Map, Vec<Position>) {
     let mut map = Map::new();
     let mut origins = vec![];
     for (y, line) in input.lines().enumerate() {
@@ -182,22 +233,57 @@ fn parse_input(filename: &str) -> (Map, Vec<Position>) {
     (map, origins)
 }
 
+/// Walls off the origin and its neighbours in a plus shape, then puts a robot on each diagonal
+fn split_into_quadrants(map: &Map, origin: Position) -> (Map, Vec<Position>) {
+    let mut map = map.clone();
+    let (x, y) = origin;
+    for pos in &[(x, y), (x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)] {
+        map.insert(*pos, Tile::new(TileKind::WALL, None, None));
+    }
+    let origins = vec![
+        (x - 1, y - 1),
+        (x + 1, y - 1),
+        (x - 1, y + 1),
+        (x + 1, y + 1),
+    ];
+    for pos in &origins {
+        map.insert(*pos, Tile::new(TileKind::ORIGIN, None, None));
+    }
+    (map, origins)
+}
+
+/// Set of keys with one bit per letter, bit 0 being `a`
+type KeySet = u32;
+
+fn key_bit(c: char) -> KeySet {
+    1 << (c as u8 - b'a')
+}
+
 #[derive(Debug, Clone)]
 struct Key {
     distances: HashMap<char, i64>,
     distance_to_origin: i64,
-    requirements: HashSet<char>,
+    requirements: KeySet,
 }
 
 impl Key {
-    fn can_unlock(&self, found_keys: &HashSet<char>) -> bool {
-        self.requirements.is_subset(found_keys)
+    fn can_unlock(&self, found_keys: KeySet) -> bool {
+        self.requirements & !found_keys == 0
     }
 }
 
+/// Counters from the key search, for checking how well the cache is doing
+#[derive(Clone, Copy, Debug, Default)]
+struct SearchStats {
+    calls: usize,
+    cache_hits: usize,
+    cache_size: usize,
+}
+
 struct KeySolver {
     keys: Vec<HashMap<char, Key>>,
-    cache: HashMap<(char, i32), i64>,
+    cache: HashMap<(char, KeySet), i64>,
+    stats: SearchStats,
 }
 
 impl KeySolver {
@@ -208,10 +294,12 @@ impl KeySolver {
         KeySolver {
             keys,
             cache: HashMap::new(),
+            stats: SearchStats::default(),
         }
     }
 
     fn generate_keys(map: &Map, origins: &Vec<Position>) -> Vec<HashMap<char, Key>> {
+        let has_doors = map.values().any(|t| matches!(t.kind, TileKind::DOOR(_)));
         let mut keyring = vec![];
         for origin in origins {
             let mut solved_keys: HashMap<char, Key> = HashMap::new();
@@ -241,25 +329,28 @@ impl KeySolver {
 
                 let mut current_pos = *origin;
                 let mut requirements: HashSet<char> = HashSet::new();
-                loop {
-                    let tile = solved_map.map.get(&current_pos).unwrap();
-                    match tile.kind {
-                        TileKind::DOOR(c) => {
-                            requirements.insert(c);
+                // Without doors every key is open from the start, so there's no path worth tracing back
+                if has_doors {
+                    loop {
+                        let tile = solved_map.map.get(&current_pos).unwrap();
+                        match tile.kind {
+                            TileKind::DOOR(c) => {
+                                requirements.insert(c);
+                            }
+                            _ => {}
+                        }
+                        if let Some(pos) = tile.parent {
+                            current_pos = pos;
+                        } else {
+                            break;
                         }
-                        _ => {}
-                    }
-                    if let Some(pos) = tile.parent {
-                        current_pos = pos;
-                    } else {
-                        break;
                     }
                 }
                 solved_keys.insert(
                     key.1,
                     Key {
                         distances,
-                        requirements,
+                        requirements: KeySolver::keyset_as_bitfield(&requirements),
                         distance_to_origin: key.2,
                     },
                 );
@@ -267,65 +358,72 @@ impl KeySolver {
             keyring.push(solved_keys);
         }
 
+        // Nothing for the requirements to chain through either
+        if !has_doors {
+            return keyring;
+        }
+
         let other_keyring = keyring.clone();
         for (i, keys) in keyring.iter_mut().enumerate() {
             for (_, key) in keys {
                 fn find_requirements(
-                    requirements: &HashSet<char>,
+                    requirements: KeySet,
                     keyring: &Vec<HashMap<char, Key>>,
-                ) -> HashSet<char> {
-                    let mut full_requirements = requirements.clone();
-                    for requirement in requirements {
-                        for keys in keyring {
-                            if let Some(key) = keys.get(&requirement) {
-                                full_requirements
-                                    .extend(find_requirements(&key.requirements, keyring));
+                ) -> KeySet {
+                    let mut full_requirements = requirements;
+                    for keys in keyring {
+                        for (name, key) in keys {
+                            if requirements & key_bit(*name) != 0 {
+                                full_requirements |= find_requirements(key.requirements, keyring);
                             }
                         }
                     }
                     full_requirements
                 }
-                let full_requirements = find_requirements(&key.requirements, &other_keyring);
-                let owned_keys = &other_keyring[i].keys().cloned().collect::<HashSet<char>>();
-                key.requirements = owned_keys
-                    .intersection(&full_requirements)
-                    .cloned()
-                    .collect::<HashSet<char>>();
+                let full_requirements = find_requirements(key.requirements, &other_keyring);
+                let owned_keys = other_keyring[i]
+                    .keys()
+                    .fold(0, |owned, name| owned | key_bit(*name));
+                key.requirements = owned_keys & full_requirements;
             }
         }
         keyring
     }
 
-    fn keyset_as_bitfield(set: &HashSet<char>) -> i32 {
-        let mut field = 0;
-        for c in set {
-            field |= 1 << (*c as u8 - 'a' as u8);
-        }
-        field
+    fn keyset_as_bitfield(set: &HashSet<char>) -> KeySet {
+        set.iter().fold(0, |field, c| field | key_bit(*c))
     }
 
-    fn find_keys(&mut self) -> i64 {
+    fn find_keys_single(&mut self, quadrant: usize) -> i64 {
+        self.find_keys_from(quadrant, &HashSet::new())
+    }
+
+    /// Shortest distance to collect the rest of a quadrant's keys, starting from its origin with `held` already in hand
+    fn find_keys_from(&mut self, quadrant: usize, held: &HashSet<char>) -> i64 {
         fn find(
             solver: &mut KeySolver,
             keys: &HashMap<char, Key>,
-            found_keys: HashSet<char>,
+            found_keys: KeySet,
             cost: i64,
             from: Option<char>,
         ) -> i64 {
+            solver.stats.calls += 1;
             let mut best: Option<i64> = None;
             for (name, key) in keys
                 .iter()
-                .filter(|(c, k)| !found_keys.contains(c) && k.can_unlock(&found_keys))
+                .filter(|(c, k)| found_keys & key_bit(**c) == 0 && k.can_unlock(found_keys))
             {
-                let mut next_found_keys = found_keys.clone();
-                next_found_keys.insert(*name);
+                let next_found_keys = found_keys | key_bit(*name);
                 let next_cost = match from {
                     Some(c) => *key.distances.get(&c).unwrap(),
                     None => key.distance_to_origin,
                 };
-                let cache_key = (*name, KeySolver::keyset_as_bitfield(&next_found_keys));
+                let cache_key = (*name, next_found_keys);
                 let found_cost = match solver.cache.get(&cache_key).clone() {
-                    Some(c) => *c + cost + next_cost,
+                    Some(c) => {
+                        solver.stats.cache_hits += 1;
+                        *c + cost + next_cost
+                    }
                     None => {
                         let c = find(solver, keys, next_found_keys, cost + next_cost, Some(*name));
                         solver.cache.insert(cache_key, c - cost - next_cost);
@@ -339,18 +437,305 @@ impl KeySolver {
             }
             best.unwrap_or(cost)
         }
-        let keys = self.keys.clone();
-        keys.iter()
-            .map(|key| find(self, key, HashSet::new(), 0, None))
+        let keys = self.keys[quadrant].clone();
+        find(self, &keys, KeySolver::keyset_as_bitfield(held), 0, None)
+    }
+
+    /// Keys in the order one shortest route collects them, read back from the search cache. When several keys start
+    /// an equally short route the earliest name is taken
+    #[cfg(test)]
+    fn find_key_order(&mut self, quadrant: usize) -> Vec<char> {
+        let mut remaining = self.find_keys_single(quadrant);
+        let keys = &self.keys[quadrant];
+        let mut order: Vec<char> = Vec::new();
+        let mut found_keys: KeySet = 0;
+        while order.len() < keys.len() {
+            let mut candidates = keys
+                .iter()
+                .filter(|(c, k)| found_keys & key_bit(**c) == 0 && k.can_unlock(found_keys))
+                .map(|(name, key)| {
+                    let step = match order.last() {
+                        Some(c) => key.distances[c],
+                        None => key.distance_to_origin,
+                    };
+                    (*name, step)
+                })
+                .collect::<Vec<_>>();
+            candidates.sort();
+            let (name, step) = candidates
+                .into_iter()
+                .find(|(name, step)| {
+                    step + self.cache[&(*name, found_keys | key_bit(*name))] == remaining
+                })
+                .expect("Search cache has no shortest route");
+            remaining -= step;
+            found_keys |= key_bit(name);
+            order.push(name);
+        }
+        order
+    }
+
+    fn find_keys(&mut self) -> i64 {
+        (0..self.keys.len())
+            .map(|quadrant| self.find_keys_single(quadrant))
             .sum()
     }
+
+    /// Same as `find_keys`, also returning the search counters for this run. The cache is cleared first so every run
+    /// is measured from scratch.
+    #[allow(dead_code)]
+    fn find_keys_stats(&mut self) -> (i64, SearchStats) {
+        self.cache.clear();
+        self.stats = SearchStats::default();
+        let distance = self.find_keys();
+        self.stats.cache_size = self.cache.len();
+        (distance, self.stats)
+    }
 }
 
 fn main() {
     let (map, origins) = parse_input("input");
     let mut solver = KeySolver::new(&map, &origins);
-    println!("18-1:\n{}", solver.find_keys());
-    let (map, origins) = parse_input("input2");
+    println!("18-1:\n{}", solver.find_keys_single(0));
+    let (map, origins) = split_into_quadrants(&map, origins[0]);
     let mut solver = KeySolver::new(&map, &origins);
     println!("18-2:\n{}", solver.find_keys());
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solver(maze: &str) -> KeySolver {
+        let (map, origins) = parse_str(maze);
+        KeySolver::new(&map, &origins)
+    }
+
+    #[test]
+    fn single_robot_examples() {
+        let maze = "\
+#########
+#b.A.@.a#
+#########";
+        assert_eq!(solver(maze).find_keys_single(0), 8);
+        let maze = "\
+########################
+#f.D.E.e.C.b.A.@.a.B.c.#
+######################.#
+#d.....................#
+########################";
+        assert_eq!(solver(maze).find_keys_single(0), 86);
+    }
+
+    #[test]
+    fn quadrants_match_hand_edited_map() {
+        let (map, origins) = parse_str(
+            "\
+#######
+#a.#Cd#
+##...##
+##.@.##
+##...##
+#cB#Ab#
+#######",
+        );
+        let (expected_map, expected_origins) = parse_str(
+            "\
+#######
+#a.#Cd#
+##@#@##
+#######
+##@#@##
+#cB#Ab#
+#######",
+        );
+        let (map, split_origins) = split_into_quadrants(&map, origins[0]);
+        assert_eq!(split_origins, expected_origins);
+        assert_eq!(map.len(), expected_map.len());
+        for (pos, tile) in &expected_map {
+            assert_eq!(map[pos].kind, tile.kind, "Tile at {:?}", pos);
+        }
+        assert_eq!(KeySolver::new(&map, &split_origins).find_keys(), 8);
+    }
+
+    #[test]
+    fn tile_kinds_from_chars() {
+        assert_eq!(TileKind::try_from('#').unwrap(), TileKind::WALL);
+        assert_eq!(TileKind::try_from('.').unwrap(), TileKind::EMPTY);
+        assert_eq!(TileKind::try_from('@').unwrap(), TileKind::ORIGIN);
+        assert_eq!(TileKind::try_from('k').unwrap(), TileKind::KEY('k'));
+        assert_eq!(TileKind::try_from('K').unwrap(), TileKind::DOOR('k'));
+        assert!(matches!(TileKind::try_from('?'), Err(InvalidTile('?'))));
+        assert!(matches!(TileKind::try_from('1'), Err(InvalidTile('1'))));
+    }
+
+    #[test]
+    fn key_order_is_reproducible() {
+        let maze = "\
+########################
+#...............b.C.D.f#
+#.######################
+#.....@.a.B.c.d.A.e.F.g#
+########################";
+        // Every solver hashes its keys with a fresh random seed, so repeated runs would expose any order dependence
+        for _ in 0..8 {
+            assert_eq!(
+                solver(maze).find_key_order(0),
+                vec!['b', 'a', 'c', 'd', 'f', 'e', 'g']
+            );
+            // Either key first is 6 steps, so the earlier name wins
+            assert_eq!(
+                solver("#######\n#a.@.b#\n#######").find_key_order(0),
+                vec!['a', 'b']
+            );
+        }
+    }
+
+    #[test]
+    fn astar_matches_dijkstra_with_fewer_expansions() {
+        let size = 41;
+        let maze = (0..size)
+            .map(|y| {
+                (0..size)
+                    .map(|x| match (x, y) {
+                        (20, 20) => '@',
+                        (0, _) | (_, 0) => '#',
+                        _ if x == size - 1 || y == size - 1 => '#',
+                        _ => '.',
+                    })
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        let (map, origins) = parse_str(&maze);
+        let goal = (35, 5);
+
+        let mut dijkstra = MazeSolver::new(&map, origins[0]);
+        dijkstra.solve(false);
+        let mut astar = MazeSolver::new(&map, origins[0]);
+        let cost = astar.solve_astar(Some(goal), false);
+        assert_eq!(cost, Some(30));
+        assert_eq!(cost, dijkstra.map[&goal].cost);
+        assert_eq!(dijkstra.expanded, 39 * 39);
+        assert!(
+            astar.expanded < dijkstra.expanded / 4,
+            "A* expanded {}",
+            astar.expanded
+        );
+
+        let mut fallback = MazeSolver::new(&map, origins[0]);
+        assert_eq!(fallback.solve_astar(None, false), None);
+        assert_eq!(fallback.map[&goal].cost, Some(30));
+    }
+
+    #[test]
+    fn search_stats_show_cache_hits() {
+        let maze = "\
+########################
+#...............b.C.D.f#
+#.######################
+#.....@.a.B.c.d.A.e.F.g#
+########################";
+        let mut solver = solver(maze);
+        let (distance, stats) = solver.find_keys_stats();
+        assert_eq!(distance, 132);
+        assert!(stats.cache_hits > 0);
+        // Every call other than the first is a cache miss on the way down
+        assert_eq!(stats.cache_size, solver.cache.len());
+        assert_eq!(stats.calls, stats.cache_size + 1);
+
+        let (distance, again) = solver.find_keys_stats();
+        assert_eq!(distance, 132);
+        assert_eq!(
+            (again.calls, again.cache_hits, again.cache_size),
+            (stats.calls, stats.cache_hits, stats.cache_size)
+        );
+    }
+
+    #[test]
+    fn held_keys_shorten_the_search() {
+        let maze = "\
+#########
+#b.A.@.a#
+#########";
+        let held = |keys: &str| keys.chars().collect::<HashSet<char>>();
+        assert_eq!(solver(maze).find_keys_from(0, &held("")), 8);
+        // Holding a opens the door, so b is straight through it
+        assert_eq!(solver(maze).find_keys_from(0, &held("a")), 4);
+        assert_eq!(solver(maze).find_keys_from(0, &held("b")), 2);
+        assert_eq!(solver(maze).find_keys_from(0, &held("ab")), 0);
+    }
+
+    #[test]
+    fn key_masks_on_published_examples() {
+        let maze = "\
+########################
+#f.D.E.e.C.b.A.@.a.B.c.#
+######################.#
+#d.....................#
+########################";
+        let keys = &solver(maze).keys[0];
+        let mask = |names: &str| names.chars().fold(0, |mask, c| mask | key_bit(c));
+        assert_eq!(key_bit('a'), 1);
+        assert_eq!(key_bit('z'), 1 << 25);
+        assert_eq!(keys[&'a'].requirements, 0);
+        assert_eq!(keys[&'b'].requirements, mask("a"));
+        assert_eq!(keys[&'e'].requirements, mask("abc"));
+        assert_eq!(keys[&'f'].requirements, mask("abcde"));
+        assert!(keys[&'e'].can_unlock(mask("abcf")));
+        assert!(!keys[&'e'].can_unlock(mask("abdf")));
+
+        let maze = "\
+########################
+#@..............ac.GI.b#
+###d#e#f################
+###A#B#C################
+###g#h#i################
+########################";
+        assert_eq!(solver(maze).find_keys_single(0), 81);
+        let maze = "\
+#################
+#i.G..c...e..H.p#
+########.########
+#j.A..b...f..D.o#
+########@########
+#k.E..a...g..B.n#
+########.########
+#l.F..d...h..C.m#
+#################";
+        assert_eq!(solver(maze).find_keys_single(0), 136);
+    }
+
+    #[test]
+    fn door_free_mazes_have_no_requirements() {
+        let maze = "\
+##########
+#a...@..b#
+##.#######
+##c......#
+##########";
+        let mut solver = solver(maze);
+        assert!(solver.keys[0].values().all(|key| key.requirements == 0));
+        // Grabbing b first then sweeping left past the start is 3 + 7 + 3, either other key first is 15
+        assert_eq!(solver.find_keys_single(0), 13);
+
+        let (map, origins) = parse_str(
+            "\
+#######
+#a.#.d#
+##...##
+##.@.##
+##...##
+#c.#.b#
+#######",
+        );
+        let (map, origins) = split_into_quadrants(&map, origins[0]);
+        let mut solver = KeySolver::new(&map, &origins);
+        assert!(solver
+            .keys
+            .iter()
+            .flatten()
+            .all(|(_, key)| key.requirements == 0));
+        assert_eq!(solver.find_keys(), 8);
+    }
+}
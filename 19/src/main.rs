@@ -1,23 +1,73 @@
 extern crate intcode;
 
+use std::collections::HashMap;
+
 type Position = (i64, i64);
 
 fn check(rom: &Vec<i64>, pos: Position) -> bool {
-    let mut cpu = intcode::Cpu::new(rom.to_vec());
-    match cpu.run_with(&vec![pos.0, pos.1]) {
+    let mut cpu = intcode::Cpu::with_inputs(rom.to_vec(), &[pos.0, pos.1]);
+    match cpu.run() {
         Some(1) => true,
         _ => false,
     }
 }
 
+/// Caches beam checks since every one of them is a full run of the drone program
+struct BeamProbe {
+    rom: Vec<i64>,
+    cache: HashMap<Position, bool>,
+}
+
+impl BeamProbe {
+    fn new(rom: &[i64]) -> BeamProbe {
+        BeamProbe {
+            rom: rom.to_vec(),
+            cache: HashMap::new(),
+        }
+    }
+
+    fn check(&mut self, pos: Position) -> bool {
+        let rom = &self.rom;
+        *self.cache.entry(pos).or_insert_with(|| check(rom, pos))
+    }
+}
+
+/// Inclusive x range the beam covers on row `y`, or `None` if it misses the row entirely (common close to the emitter)
+#[allow(dead_code)]
+fn beam_edges(probe: &mut BeamProbe, y: i64) -> Option<(i64, i64)> {
+    // The beam fans out from the origin, so it can't be much further out than a few multiples of y
+    let limit = (y + 1) * 10;
+    let left = (0..=limit).find(|x| probe.check((*x, y)))?;
+    let mut right = left;
+    while probe.check((right + 1, y)) {
+        right += 1;
+    }
+    Some((left, right))
+}
+
+/// Draws the beam over a `width` x `height` window from the emitter, `#` where it pulls and `.` where it doesn't
+#[allow(dead_code)]
+fn render_beam(rom: &[i64], width: i64, height: i64) -> String {
+    let mut probe = BeamProbe::new(rom);
+    let mut out = String::new();
+    for y in 0..height {
+        for x in 0..width {
+            out.push(if probe.check((x, y)) { '#' } else { '.' });
+        }
+        out.push('\n');
+    }
+    out
+}
+
 fn main() {
     let rom = intcode::parse_rom("input");
 
     println!("19-1:");
+    let mut probe = BeamProbe::new(&rom);
     let mut count = 0;
     for x in 0..50 {
         for y in 0..50 {
-            if check(&rom, (x, y)) {
+            if probe.check((x, y)) {
                 count += 1;
             }
         }
@@ -27,16 +77,45 @@ fn main() {
     let mut origin = (0, 0);
     let length = 99;
     loop {
-        if check(&rom, origin)
-            && check(&rom, (origin.0, origin.1 + length))
-            && check(&rom, (origin.0 + length, origin.1))
+        if probe.check(origin)
+            && probe.check((origin.0, origin.1 + length))
+            && probe.check((origin.0 + length, origin.1))
         {
             break;
         }
         origin.1 += 1;
-        while !check(&rom, (origin.0, origin.1 + length)) {
+        while !probe.check((origin.0, origin.1 + length)) {
             origin.0 += 1;
         }
     }
     println!("{}", origin.0 * 10_000 + origin.1);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn beam_edges_widen_with_distance() {
+        let mut probe = BeamProbe::new(&intcode::parse_rom("input"));
+        let mut previous = beam_edges(&mut probe, 10).unwrap();
+        for y in 11..100 {
+            let (left, right) = beam_edges(&mut probe, y).unwrap();
+            assert!(left >= previous.0 && right >= previous.1, "Row {}", y);
+            assert!(!probe.check((left - 1, y)) && !probe.check((right + 1, y)));
+            previous = (left, right);
+        }
+        let first = beam_edges(&mut probe, 10).unwrap();
+        assert!(previous.1 - previous.0 > first.1 - first.0);
+    }
+
+    #[test]
+    fn render_beam_matches_part_1_count() {
+        let beam = render_beam(&intcode::parse_rom("input"), 50, 50);
+        assert_eq!(beam.matches('#').count(), 209);
+        assert_eq!(beam.lines().count(), 50);
+        assert!(beam.lines().all(|row| row.len() == 50));
+        // The emitter is always caught in its own beam
+        assert!(beam.starts_with('#'));
+    }
+}
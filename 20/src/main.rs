@@ -27,6 +27,18 @@ impl Tile {
 
 type Map = HashMap<Position, Tile>;
 
+/// One end of a portal, named by its label and whether it's on the inner edge. AA and ZZ only have an outer end.
+type PortalEnd = ((char, char), bool);
+
+fn portal_end(kind: TileKind) -> Option<PortalEnd> {
+    match kind {
+        TileKind::ENTRANCE => Some((('A', 'A'), false)),
+        TileKind::EXIT => Some((('Z', 'Z'), false)),
+        TileKind::WARP(id, inner) => Some((id, inner)),
+        _ => None,
+    }
+}
+
 struct OrderedQueue<T> {
     queue: BTreeMap<i64, VecDeque<T>>,
 }
@@ -68,12 +80,31 @@ where
     }
 }
 
+#[derive(Debug)]
+enum SolveError {
+    Unreachable,
+    DepthLimitExceeded(usize),
+}
+
+impl std::fmt::Display for SolveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SolveError::Unreachable => write!(f, "Could not find exit"),
+            SolveError::DepthLimitExceeded(depth) => {
+                write!(f, "Could not find exit within {} layers", depth)
+            }
+        }
+    }
+}
+
 struct MazeSolver {
     origin: Position,
     unsolved: OrderedQueue<(Position, usize)>,
     map: Map,
     inner_maps: Vec<Map>,
     inner_exit_cost: Option<i64>,
+    max_depth: usize,
+    depth_limit_exceeded: bool,
 }
 
 impl MazeSolver {
@@ -86,13 +117,116 @@ impl MazeSolver {
             map: map,
             inner_maps: vec![],
             inner_exit_cost: None,
+            max_depth: MazeSolver::DEFAULT_MAX_DEPTH,
+            depth_limit_exceeded: false,
+        }
+    }
+
+    fn entrance(&self) -> Position {
+        self.origin
+    }
+
+    #[cfg(test)]
+    fn exit(&self) -> Position {
+        *self
+            .map
+            .iter()
+            .find(|(_, tile)| tile.kind == TileKind::EXIT)
+            .expect("Could not find exit")
+            .0
+    }
+
+    #[cfg(test)]
+    fn solve(&mut self) -> Option<i64> {
+        self.solve_position(self.entrance());
+        self.map[&self.exit()].cost
+    }
+
+    /// Every position walked from the entrance to the exit, with portal jumps as a single step between both ends
+    #[cfg(test)]
+    fn solve_path(&mut self) -> Option<Vec<Position>> {
+        self.solve()?;
+        let mut path = vec![self.exit()];
+        while let Some(parent) = self.map[path.last().unwrap()].parent {
+            path.push(parent);
         }
+        path.reverse();
+        Some(path)
     }
 
-    fn solve(&mut self) {
-        self.solve_position(self.origin);
+    /// Walking distance from every portal end to each other one it can reach without teleporting
+    fn portal_graph(&self) -> HashMap<PortalEnd, Vec<(PortalEnd, i64)>> {
+        let mut graph = HashMap::new();
+        for (start, tile) in &self.map {
+            let from = match portal_end(tile.kind) {
+                Some(end) => end,
+                None => continue,
+            };
+            let mut edges = vec![];
+            let mut distances = HashMap::new();
+            let mut queue = VecDeque::new();
+            distances.insert(*start, 0);
+            queue.push_back(*start);
+            while let Some(pos) = queue.pop_front() {
+                let cost = distances[&pos];
+                if let Some(to) = portal_end(self.map[&pos].kind) {
+                    if pos != *start {
+                        edges.push((to, cost));
+                    }
+                }
+                for next_pos in [
+                    (pos.0, pos.1 - 1),
+                    (pos.0, pos.1 + 1),
+                    (pos.0 - 1, pos.1),
+                    (pos.0 + 1, pos.1),
+                ]
+                .iter()
+                {
+                    match self.map.get(next_pos) {
+                        Some(tile) if tile.kind != TileKind::WALL => {}
+                        _ => continue,
+                    }
+                    if !distances.contains_key(next_pos) {
+                        distances.insert(*next_pos, cost + 1);
+                        queue.push_back(*next_pos);
+                    }
+                }
+            }
+            graph.insert(from, edges);
+        }
+        graph
     }
 
+    /// Same answer as `solve`, but searching the much smaller graph of portals instead of every tile
+    fn solve_portal_graph(&self) -> Option<i64> {
+        let graph = self.portal_graph();
+        let (entrance, exit) = ((('A', 'A'), false), (('Z', 'Z'), false));
+        let mut costs: HashMap<PortalEnd, i64> = HashMap::new();
+        let mut unsolved = OrderedQueue::new();
+        unsolved.add(0, &entrance);
+        while let Some((cost, end)) = unsolved.pop() {
+            if end == exit {
+                return Some(cost);
+            }
+            if costs.contains_key(&end) {
+                continue;
+            }
+            costs.insert(end, cost);
+            let mut edges = graph.get(&end).cloned().unwrap_or_default();
+            // Stepping into a portal takes you to its other end
+            if end != entrance {
+                edges.push(((end.0, !end.1), 1));
+            }
+            for (next, distance) in edges {
+                if !costs.contains_key(&next) {
+                    unsolved.add(cost + distance, &next);
+                }
+            }
+        }
+        None
+    }
+
+    #[cfg(test)]
     fn solve_position(&mut self, pos: Position) {
         let current_tile = self.map.get(&pos).unwrap();
         let current_tile_cost = current_tile.cost.expect("Cannot solve for a wall");
@@ -121,9 +255,8 @@ impl MazeSolver {
         {
             let next_tile = match self.map.get_mut(&next_pos).cloned() {
                 Some(mut tile) => {
-                    match tile.kind {
-                        TileKind::WALL => continue,
-                        _ => {}
+                    if tile.kind == TileKind::WALL {
+                        continue;
                     }
                     if tile.cost.is_none() || tile.cost.unwrap() > current_tile_cost + 1 {
                         tile.cost = Some(current_tile_cost + 1);
@@ -141,28 +274,45 @@ impl MazeSolver {
         }
     }
 
-    fn new_layer(&self) -> Map {
-        let mut next_map = self.map.clone();
-        for (_, tile) in &mut next_map {
-            tile.cost = None;
-            tile.parent = None;
+    /// Tile at `pos` on layer `depth`. Layers only hold the tiles reached on them and read everything else from the
+    /// shared map, so each new layer starts out empty rather than as a copy of the whole maze.
+    fn layer_tile(&self, depth: usize, pos: &Position) -> Option<Tile> {
+        match self.inner_maps[depth].get(pos) {
+            Some(tile) => Some(*tile),
+            None => self
+                .map
+                .get(pos)
+                .map(|tile| Tile::new(tile.kind, None, None)),
         }
-        next_map
+    }
+
+    /// Deep enough for any real maze, while keeping a maze that only ever descends from running forever
+    const DEFAULT_MAX_DEPTH: usize = 500;
+
+    /// Gives up on the recursive maze past `max_depth` layers instead of `DEFAULT_MAX_DEPTH`
+    #[allow(dead_code)]
+    fn with_max_depth(mut self, max_depth: usize) -> MazeSolver {
+        self.max_depth = max_depth;
+        self
     }
 
     /// Since a recursive maze can go on forever, this will terminate early once an exit is found leaving an inexhaustive map state.
-    fn solve_recursive(&mut self) -> i64 {
+    fn solve_recursive(&mut self) -> Result<i64, SolveError> {
         self.inner_exit_cost = None;
+        self.depth_limit_exceeded = false;
         if self.inner_maps.len() == 0 {
-            let mut next_map = self.new_layer();
-            next_map.insert(self.origin, Tile::new(TileKind::ENTRANCE, Some(0), None));
+            let mut next_map = Map::new();
+            next_map.insert(
+                self.entrance(),
+                Tile::new(TileKind::ENTRANCE, Some(0), None),
+            );
             self.inner_maps.push(next_map);
         }
-        self.solve_position_recursive(self.origin, 0);
+        self.solve_position_recursive(self.entrance(), 0);
         // Ironically the recursive maze needs a non-recursive solution (stack overflow)
         loop {
             if let Some(cost) = self.inner_exit_cost {
-                return cost;
+                return Ok(cost);
             }
             if let Some((_, next)) = self.unsolved.pop() {
                 self.solve_position_recursive(next.0, next.1);
@@ -170,15 +320,19 @@ impl MazeSolver {
                 break;
             }
         }
-        panic!("Could not find exit");
+        if self.depth_limit_exceeded {
+            Err(SolveError::DepthLimitExceeded(self.max_depth))
+        } else {
+            Err(SolveError::Unreachable)
+        }
     }
 
     fn solve_position_recursive(&mut self, pos: Position, depth: usize) {
-        while self.inner_maps.len() <= depth + 1 {
-            self.inner_maps.push(self.new_layer());
+        while self.inner_maps.len() <= min(depth + 1, self.max_depth) {
+            self.inner_maps.push(Map::new());
         }
 
-        let current_tile = self.inner_maps[depth].get(&pos).unwrap();
+        let current_tile = self.layer_tile(depth, &pos).unwrap();
         let current_tile_cost = current_tile.cost.expect("Cannot solve for a wall");
         if current_tile.kind == TileKind::EXIT && depth == 0 {
             self.inner_exit_cost = Some(current_tile_cost);
@@ -186,18 +340,23 @@ impl MazeSolver {
         }
 
         if let TileKind::WARP(id, inner) = current_tile.kind {
-            if inner == true || depth > 0 {
+            if inner && depth >= self.max_depth {
+                self.depth_limit_exceeded = true;
+            } else if inner == true || depth > 0 {
                 let next_depth = if inner { depth + 1 } else { depth - 1 };
-                let (exit_pos, mut exit_tile) = self.inner_maps[next_depth]
-                    .iter_mut()
-                    .filter(|(_, t)| t.kind == TileKind::WARP(id, !inner))
-                    .next()
-                    .unwrap();
+                let exit_pos = *self
+                    .map
+                    .iter()
+                    .find(|(_, t)| t.kind == TileKind::WARP(id, !inner))
+                    .unwrap()
+                    .0;
+                let mut exit_tile = self.layer_tile(next_depth, &exit_pos).unwrap();
                 if exit_tile.cost.is_none() || exit_tile.cost.unwrap() > current_tile_cost + 1 {
                     exit_tile.cost = Some(current_tile_cost + 1);
                     exit_tile.parent = Some(pos);
                     self.unsolved
-                        .add(exit_tile.cost.unwrap(), &(*exit_pos, next_depth));
+                        .add(exit_tile.cost.unwrap(), &(exit_pos, next_depth));
+                    self.inner_maps[next_depth].insert(exit_pos, exit_tile);
                 }
             }
         };
@@ -210,7 +369,7 @@ impl MazeSolver {
         ]
         .iter()
         {
-            let next_tile = match self.inner_maps[depth].get_mut(&next_pos).cloned() {
+            let next_tile = match self.layer_tile(depth, next_pos) {
                 Some(mut tile) => {
                     match tile.kind {
                         TileKind::WALL => continue,
@@ -229,8 +388,8 @@ impl MazeSolver {
         }
     }
 
-    #[allow(dead_code)]
-    fn print_map(&self) {
+    /// Draws every tile in the map's bounding box using `display` for each one
+    fn render(&self, display: impl Fn(Option<&Tile>) -> char) -> String {
         let (mut min_x, mut max_x, mut min_y, mut max_y) = (0, 0, 0, 0);
         for (x, y) in self.map.keys() {
             min_x = min(min_x, *x);
@@ -238,24 +397,52 @@ impl MazeSolver {
             max_x = max(max_x, *x);
             max_y = max(max_y, *y);
         }
+        let mut output = String::new();
         for y in min_y..=max_y {
             for x in min_x..=max_x {
-                let tile = self.map.get(&(x, y));
-                let tile_display = match tile {
-                    Some(t) => match t.kind {
-                        TileKind::EMPTY => ' ',
-                        TileKind::WALL => '#',
-                        TileKind::ENTRANCE => '^',
-                        TileKind::EXIT => 'v',
-                        TileKind::WARP(_, true) => 'I',
-                        TileKind::WARP(_, false) => 'O',
-                    },
-                    None => '~',
-                };
-                print!("{}", tile_display);
+                output.push(display(self.map.get(&(x, y))));
             }
-            println!("");
+            output.push('\n');
         }
+        output
+    }
+
+    #[allow(dead_code)]
+    fn print_map(&self) {
+        print!(
+            "{}",
+            self.render(|tile| match tile {
+                Some(t) => match t.kind {
+                    TileKind::EMPTY => ' ',
+                    TileKind::WALL => '#',
+                    TileKind::ENTRANCE => '^',
+                    TileKind::EXIT => 'v',
+                    TileKind::WARP(_, true) => 'I',
+                    TileKind::WARP(_, false) => 'O',
+                },
+                None => '~',
+            })
+        );
+    }
+
+    /// Shows the last digit of each reached tile's cost after a (non-recursive) `solve`
+    fn render_costs(&self) -> String {
+        self.render(|tile| match tile {
+            Some(Tile {
+                kind: TileKind::WALL,
+                ..
+            }) => '#',
+            Some(Tile {
+                cost: Some(cost), ..
+            }) => std::char::from_digit((cost % 10) as u32, 10).unwrap(),
+            Some(_) => '.',
+            None => ' ',
+        })
+    }
+
+    #[allow(dead_code)]
+    fn print_costs(&self) {
+        print!("{}", self.render_costs());
     }
 }
 
@@ -269,14 +456,47 @@ fn try_tile(map: &Vec<Vec<char>>, x: i64, y: i64) -> Option<&char> {
     }
 }
 
-fn parse_input(filename: &str) -> (Map, Position) {
+#[derive(Debug)]
+enum ParseError {
+    SentinelCount { label: &'static str, count: usize },
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::SentinelCount { label, count } => {
+                write!(f, "Expected exactly one {} but found {}", label, count)
+            }
+        }
+    }
+}
+
+fn parse_input(filename: &str) -> Result<(Map, Position), ParseError> {
     let input = std::fs::read_to_string(filename).unwrap();
+    parse_str(&input)
+}
+
+/// Parses the maze along with the position of its entrance, making sure it has exactly one entrance and exit
+fn parse_str(input: &str) -> Result<(Map, Position), ParseError> {
     let mut map = Map::new();
     let input = input
         .lines()
         .map(|line| line.chars().collect::<Vec<char>>())
         .collect::<Vec<_>>();
-    let center = (input[0].len() as i64 / 2, input.len() as i64 / 2);
+    // Portals with a walkable tile on the maze's outermost ring are outer, everything else borders the hole
+    let (mut min_x, mut max_x, mut min_y, mut max_y) = (i64::MAX, i64::MIN, i64::MAX, i64::MIN);
+    for (y, row) in input.iter().enumerate() {
+        for (x, c) in row.iter().enumerate() {
+            if *c == '#' || *c == '.' {
+                min_x = min(min_x, x as i64);
+                max_x = max(max_x, x as i64);
+                min_y = min(min_y, y as i64);
+                max_y = max(max_y, y as i64);
+            }
+        }
+    }
+    let is_inner =
+        |pos: Position| pos.0 != min_x && pos.0 != max_x && pos.1 != min_y && pos.1 != max_y;
     for (y, row) in input.iter().enumerate() {
         for (x, c) in row.iter().enumerate() {
             let (x, y) = (x as i64, y as i64);
@@ -301,12 +521,7 @@ fn parse_input(filename: &str) -> (Map, Position) {
                         } else if *c == 'Z' && *c2 == 'Z' {
                             TileKind::EXIT
                         } else {
-                            let inner = if pos.0 < center.0 {
-                                pos.0 < x
-                            } else {
-                                pos.0 > x
-                            };
-                            TileKind::WARP((*c, *c2), inner)
+                            TileKind::WARP((*c, *c2), is_inner(pos))
                         };
                         map.insert(pos, Tile::new(kind, None, None));
                     }
@@ -325,12 +540,7 @@ fn parse_input(filename: &str) -> (Map, Position) {
                         } else if *c == 'Z' && *c2 == 'Z' {
                             TileKind::EXIT
                         } else {
-                            let inner = if pos.1 < center.1 {
-                                pos.1 < y
-                            } else {
-                                pos.1 > y
-                            };
-                            TileKind::WARP((*c, *c2), inner)
+                            TileKind::WARP((*c, *c2), is_inner(pos))
                         };
                         map.insert(pos, Tile::new(kind, None, None));
                     }
@@ -338,31 +548,301 @@ fn parse_input(filename: &str) -> (Map, Position) {
             }
         }
     }
-    let origin = *map
-        .iter()
-        .filter(|(_, v)| v.kind == TileKind::ENTRANCE)
-        .next()
-        .expect("Could not find entrance")
-        .0;
-    (map, origin)
+    let sentinels = |kind: TileKind| {
+        map.iter()
+            .filter(|(_, tile)| tile.kind == kind)
+            .map(|(pos, _)| *pos)
+            .collect::<Vec<_>>()
+    };
+    let entrances = sentinels(TileKind::ENTRANCE);
+    let exits = sentinels(TileKind::EXIT);
+    if entrances.len() != 1 {
+        return Err(ParseError::SentinelCount {
+            label: "AA",
+            count: entrances.len(),
+        });
+    }
+    if exits.len() != 1 {
+        return Err(ParseError::SentinelCount {
+            label: "ZZ",
+            count: exits.len(),
+        });
+    }
+    Ok((map, entrances[0]))
 }
 
 fn main() {
-    let (map, origin) = parse_input("input");
-    let mut solver = MazeSolver::new(&map, origin);
-    solver.solve();
-    println!(
-        "20-1:\n{}",
-        solver
-            .map
-            .values()
-            .filter(|t| t.kind == TileKind::EXIT)
-            .next()
-            .unwrap()
-            .cost
-            .unwrap()
-    );
-    let (map, origin) = parse_input("input");
+    let (map, origin) = parse_input("input").unwrap_or_else(|e| panic!("Invalid maze: {}", e));
+    let solver = MazeSolver::new(&map, origin);
+    match solver.solve_portal_graph() {
+        Some(cost) => println!("20-1:\n{}", cost),
+        None => println!("20-1:\nCould not find exit"),
+    }
+    let (map, origin) = parse_input("input").unwrap_or_else(|e| panic!("Invalid maze: {}", e));
     let mut solver = MazeSolver::new(&map, origin);
-    println!("20-2:\n{}", solver.solve_recursive());
+    match solver.solve_recursive() {
+        Ok(cost) => println!("20-2:\n{}", cost),
+        Err(err) => println!("20-2:\n{}", err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "         A           
+         A           
+  #######.#########  
+  #######.........#  
+  #######.#######.#  
+  #######.#######.#  
+  #######.#######.#  
+  #####  B    ###.#  
+BC...##  C    ###.#  
+  ##.##       ###.#  
+  ##...DE  F  ###.#  
+  #####    G  ###.#  
+  #########.#####.#  
+DE..#######...###.#  
+  #.#########.###.#  
+FG..#########.....#  
+  ###########.#####  
+             Z       
+             Z       
+";
+
+    /// ZZ is walled off, and the only portal leads from the ring back into the same ring one layer deeper
+    const DESCENDING_EXAMPLE: &str = "       A           
+       A           
+  #####.#########  
+  #.............#  
+  #.###.#######.#  
+  #.#  X      #.#  
+  #.#  Y      #.#  
+XY..#         #.#  
+  #.#         #.#  
+  #.###########.#  
+  #..........#..#  
+  ###########.###  
+             Z     
+             Z     
+";
+
+    /// Has portals on every edge of both the outer ring and the hole
+    const RECURSIVE_EXAMPLE: &str = "             Z L X W       C                 
+             Z P Q B       K                 
+  ###########.#.#.#.#######.###############  
+  #...#.......#.#.......#.#.......#.#.#...#  
+  ###.#.#.#.#.#.#.#.###.#.#.#######.#.#.###  
+  #.#...#.#.#...#.#.#...#...#...#.#.......#  
+  #.###.#######.###.###.#.###.###.#.#######  
+  #...#.......#.#...#...#.............#...#  
+  #.#########.#######.#.#######.#######.###  
+  #...#.#    F       R I       Z    #.#.#.#  
+  #.###.#    D       E C       H    #.#.#.#  
+  #.#...#                           #...#.#  
+  #.###.#                           #.###.#  
+  #.#....OA                       WB..#.#..ZH
+  #.###.#                           #.#.#.#  
+CJ......#                           #.....#  
+  #######                           #######  
+  #.#....CK                         #......IC
+  #.###.#                           #.###.#  
+  #.....#                           #...#.#  
+  ###.###                           #.#.#.#  
+XF....#.#                         RF..#.#.#  
+  #####.#                           #######  
+  #......CJ                       NM..#...#  
+  ###.#.#                           #.###.#  
+RE....#.#                           #......RF
+  ###.###        X   X       L      #.#.#.#  
+  #.....#        F   Q       P      #.#.#.#  
+  ###.###########.###.#######.#########.###  
+  #.....#...#.....#.......#...#.....#.#...#  
+  #####.#.###.#######.#######.###.###.#.#.#  
+  #.......#.......#.#.#.#.#...#...#...#.#.#  
+  #####.###.#####.#.#.#.#.###.###.#.###.###  
+  #.......#.....#.#...#...............#...#  
+  #############.#.#.###.###################  
+               A O F   N                     
+               A A D   M                     
+";
+
+    /// The first example with the only path into ZZ walled off
+    fn walled_off_example() -> String {
+        let mut lines = EXAMPLE.lines().map(String::from).collect::<Vec<_>>();
+        lines[15].replace_range(13..14, "#");
+        lines.join("\n")
+    }
+
+    fn solver(input: &str) -> MazeSolver {
+        let (map, origin) = parse_str(input).unwrap();
+        MazeSolver::new(&map, origin)
+    }
+
+    #[test]
+    fn solves_example() {
+        assert_eq!(solver(EXAMPLE).solve(), Some(23));
+        assert_eq!(solver(EXAMPLE).solve_recursive().unwrap(), 26);
+    }
+
+    #[test]
+    fn unreachable_exit_is_not_a_solution() {
+        let maze = walled_off_example();
+        assert_eq!(solver(&maze).solve(), None);
+        assert!(matches!(
+            solver(&maze).solve_recursive(),
+            Err(SolveError::Unreachable)
+        ));
+    }
+
+    #[test]
+    fn portals_on_every_edge_are_classified() {
+        let (map, _) = parse_str(RECURSIVE_EXAMPLE).unwrap();
+        let warp = |pos: Position| match map[&pos].kind {
+            TileKind::WARP(id, inner) => (id, inner),
+            kind => panic!("Expected a portal at {:?} but found {:?}", pos, kind),
+        };
+        // Outer top, bottom, left and right
+        assert_eq!(warp((15, 2)), (('L', 'P'), false));
+        assert_eq!(warp((19, 34)), (('F', 'D'), false));
+        assert_eq!(warp((2, 15)), (('C', 'J'), false));
+        assert_eq!(warp((42, 13)), (('Z', 'H'), false));
+        // Inner top, bottom, left and right
+        assert_eq!(warp((13, 8)), (('F', 'D'), true));
+        assert_eq!(warp((29, 28)), (('L', 'P'), true));
+        assert_eq!(warp((8, 13)), (('O', 'A'), true));
+        assert_eq!(warp((36, 21)), (('R', 'F'), true));
+        // Every portal has exactly one end on each ring
+        let mut ends: HashMap<(char, char), Vec<bool>> = HashMap::new();
+        for tile in map.values() {
+            if let TileKind::WARP(id, inner) = tile.kind {
+                ends.entry(id).or_default().push(inner);
+            }
+        }
+        for (id, mut rings) in ends {
+            rings.sort();
+            assert_eq!(rings, vec![false, true], "Portal {:?}", id);
+        }
+        assert_eq!(solver(RECURSIVE_EXAMPLE).solve_recursive().unwrap(), 396);
+    }
+
+    #[test]
+    fn endless_descent_hits_depth_limit() {
+        let (map, origin) = parse_str(DESCENDING_EXAMPLE).unwrap();
+        assert!(matches!(
+            map[&(7, 4)].kind,
+            TileKind::WARP(('X', 'Y'), true)
+        ));
+        assert!(matches!(
+            map[&(2, 7)].kind,
+            TileKind::WARP(('X', 'Y'), false)
+        ));
+        assert_eq!(solver(DESCENDING_EXAMPLE).solve(), None);
+        let mut solver = MazeSolver::new(&map, origin).with_max_depth(5);
+        assert!(matches!(
+            solver.solve_recursive(),
+            Err(SolveError::DepthLimitExceeded(5))
+        ));
+        assert!(matches!(
+            MazeSolver::new(&map, origin).solve_recursive(),
+            Err(SolveError::DepthLimitExceeded(
+                MazeSolver::DEFAULT_MAX_DEPTH
+            ))
+        ));
+    }
+
+    #[test]
+    fn render_costs_after_solve() {
+        let mut solver = solver(EXAMPLE);
+        assert!(!solver
+            .render_costs()
+            .contains(|c: char| c.is_ascii_digit() && c != '0'));
+        solver.solve();
+        let costs = solver.render_costs();
+        let rows = costs.lines().collect::<Vec<_>>();
+        assert_eq!(rows.len(), EXAMPLE.lines().count());
+        // Entrance at AA and exit at ZZ, 23 steps apart
+        assert_eq!(rows[2].chars().nth(9), Some('0'));
+        assert_eq!(rows[16].chars().nth(13), Some('3'));
+        assert_eq!(rows[2].chars().nth(2), Some('#'));
+    }
+
+    #[test]
+    fn needs_exactly_one_entrance_and_exit() {
+        let maze = solver(EXAMPLE);
+        assert_eq!(maze.entrance(), (9, 2));
+        assert_eq!(maze.exit(), (13, 16));
+
+        let relabel = |label: &str| {
+            let mut lines = EXAMPLE.lines().map(String::from).collect::<Vec<_>>();
+            lines[17].replace_range(13..14, label);
+            lines[18].replace_range(13..14, label);
+            lines.join("\n")
+        };
+        assert!(matches!(
+            parse_str(&relabel(" ")),
+            Err(ParseError::SentinelCount {
+                label: "ZZ",
+                count: 0
+            })
+        ));
+        assert!(matches!(
+            parse_str(&relabel("A")),
+            Err(ParseError::SentinelCount {
+                label: "AA",
+                count: 2
+            })
+        ));
+    }
+
+    #[test]
+    fn portal_graph_matches_tile_search() {
+        let graph = solver(EXAMPLE).portal_graph();
+        let entrance = (('A', 'A'), false);
+        let mut edges = graph[&entrance].clone();
+        edges.sort();
+        assert_eq!(
+            edges,
+            vec![
+                ((('B', 'C'), true), 4),
+                ((('F', 'G'), true), 30),
+                ((('Z', 'Z'), false), 26)
+            ]
+        );
+        // BC's outer end is only next to DE's inner one
+        assert_eq!(graph[&(('B', 'C'), false)], vec![((('D', 'E'), true), 6)]);
+        assert_eq!(graph.len(), 8);
+
+        for maze in [EXAMPLE, RECURSIVE_EXAMPLE, &walled_off_example()].iter() {
+            assert_eq!(solver(maze).solve_portal_graph(), solver(maze).solve());
+        }
+        assert_eq!(solver(EXAMPLE).solve_portal_graph(), Some(23));
+    }
+
+    #[test]
+    fn solve_path_walks_the_exit_cost() {
+        let mut maze = solver(EXAMPLE);
+        let path = maze.solve_path().unwrap();
+        assert_eq!(path.len() as i64 - 1, maze.map[&maze.exit()].cost.unwrap());
+        assert_eq!(path.len() - 1, 23);
+        assert_eq!(path.first(), Some(&maze.entrance()));
+        assert_eq!(path.last(), Some(&maze.exit()));
+        // Every step is to a neighbouring tile, or through both ends of one portal
+        let mut jumps = 0;
+        for pair in path.windows(2) {
+            let (from, to) = (pair[0], pair[1]);
+            if (from.0 - to.0).abs() + (from.1 - to.1).abs() == 1 {
+                continue;
+            }
+            jumps += 1;
+            match (maze.map[&from].kind, maze.map[&to].kind) {
+                (TileKind::WARP(a, _), TileKind::WARP(b, _)) => assert_eq!(a, b),
+                kinds => panic!("Jumped from {:?} to {:?} over {:?}", from, to, kinds),
+            }
+        }
+        // Through BC, DE and FG
+        assert_eq!(jumps, 3);
+        assert_eq!(solver(&walled_off_example()).solve_path(), None);
+    }
 }
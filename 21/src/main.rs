@@ -1,51 +1,72 @@
 extern crate intcode;
 
+const WALK_PROGRAM: &str = "\
+    NOT B T\n\
+    OR T J\n\
+    NOT C T\n\
+    OR T J\n\
+
+    NOT A T\n\
+    OR T J\n\
+    AND D J\n\
+
+    WALK\n\
+";
+
+const RUN_PROGRAM: &str = "\
+    NOT B T\n\
+    OR T J\n\
+    NOT C T\n\
+    OR T J\n\
+
+    AND G T\n\
+    AND E T\n\
+    OR H T\n\
+    AND T J\n\
+
+    NOT A T\n\
+    OR T J\n\
+    AND D J\n\
+
+    RUN\n\
+";
+
+/// Runs a springscript program, returning the reported hull damage or `None` if the droid fell into space
+fn run_springscript(rom: &[i64], program: &str) -> Option<i64> {
+    let mut cpu = intcode::Cpu::new(rom.to_vec());
+    let input = program.bytes().map(|b| b as i64).collect();
+    let damage = cpu.ascii_outputs(&input).find_map(|output| match output {
+        intcode::AsciiOut::Value(damage) => Some(damage),
+        intcode::AsciiOut::Char(_) => None,
+    });
+    damage
+}
+
 fn main() {
     let rom = intcode::parse_rom("input");
     println!("21-1:");
-    let mut cpu = intcode::Cpu::new(rom.to_vec());
-    let code = "\
-        NOT B T\n\
-        OR T J\n\
-        NOT C T\n\
-        OR T J\n\
-
-        NOT A T\n\
-        OR T J\n\
-        AND D J\n\
-
-        WALK\n\
-    ";
-    cpu.push(&code.bytes().map(|b| b as i64).collect());
-    while let Some(c) = cpu.run() {
-        if c > std::u8::MAX as i64 {
-            println!("{}", c);
-        }
+    match run_springscript(&rom, WALK_PROGRAM) {
+        Some(damage) => println!("{}", damage),
+        None => println!("Droid fell into space"),
     }
 
     println!("21-2:");
-    let mut cpu = intcode::Cpu::new(rom.to_vec());
-    let code = "\
-        NOT B T\n\
-        OR T J\n\
-        NOT C T\n\
-        OR T J\n\
-
-        AND G T\n\
-        AND E T\n\
-        OR H T\n\
-        AND T J\n\
-
-        NOT A T\n\
-        OR T J\n\
-        AND D J\n\
-
-        RUN\n\
-    ";
-    cpu.push(&code.bytes().map(|b| b as i64).collect());
-    while let Some(c) = cpu.run() {
-        if c > std::u8::MAX as i64 {
-            println!("{}", c);
-        }
+    match run_springscript(&rom, RUN_PROGRAM) {
+        Some(damage) => println!("{}", damage),
+        None => println!("Droid fell into space"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hull_damage_on_real_rom() {
+        let rom = intcode::parse_rom("input");
+        assert_eq!(run_springscript(&rom, WALK_PROGRAM), Some(19357335));
+        assert_eq!(run_springscript(&rom, RUN_PROGRAM), Some(1140147758));
+        // Never jumping walks straight into the first hole
+        assert_eq!(run_springscript(&rom, "WALK\n"), None);
     }
 }
@@ -1,62 +1,50 @@
 extern crate num;
 use num::bigint::BigInt;
-use num::{Signed, ToPrimitive};
+use num::{Integer, One, ToPrimitive, Zero};
 
 #[derive(Debug, Clone)]
 enum DealTechnique {
     NewStack,
     Cut(BigInt),
+    /// Only a permutation when the increment is coprime with the deck length, which the puzzle's prime lengths
+    /// guarantee. Anything else is rejected with `ShuffleError::NonCoprimeIncrement`.
     Increment(BigInt),
 }
 
-impl DealTechnique {
-    fn apply(&self, deck: &Vec<BigInt>) -> Vec<BigInt> {
+#[derive(Debug)]
+enum ShuffleError {
+    /// Dealing with an increment sharing a factor with the deck length lands several cards on the same position
+    NonCoprimeIncrement { inc: BigInt, deck_length: BigInt },
+}
+
+impl std::fmt::Display for ShuffleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            DealTechnique::NewStack => deck.iter().rev().cloned().collect(),
-            DealTechnique::Cut(index) => {
-                let slice_point = if *index > BigInt::from(0) {
-                    index.clone()
-                } else {
-                    deck.len() - index.abs()
-                }
-                .to_usize()
-                .unwrap();
-                [&deck[slice_point..], &deck[..slice_point]].concat()
-            }
-            DealTechnique::Increment(inc) => {
-                let mut index = BigInt::from(0);
-                let mut next_deck = vec![BigInt::from(0); deck.len()];
-                for card in deck {
-                    next_deck[(index.clone() % BigInt::from(deck.len()))
-                        .to_usize()
-                        .unwrap()] = card.clone();
-                    index += inc;
-                }
-                next_deck
-            }
+            ShuffleError::NonCoprimeIncrement { inc, deck_length } => write!(
+                f,
+                "Increment {} is not coprime with deck length {}",
+                inc, deck_length
+            ),
         }
     }
+}
 
-    fn undo_position(&self, position: &BigInt, deck_length: &BigInt) -> BigInt {
-        match self {
-            DealTechnique::NewStack => deck_length - 1 - position,
-            DealTechnique::Cut(index) => {
-                let slice_point = if *index > BigInt::from(0) {
-                    index.clone()
-                } else {
-                    deck_length - index.abs()
-                };
-                let slice_point = deck_length - slice_point;
-                if *position < slice_point {
-                    deck_length - slice_point + position
-                } else {
-                    position - slice_point
-                }
-            }
-            DealTechnique::Increment(inc) => {
-                modular_inverse(&inc, &deck_length) * position % deck_length
+impl DealTechnique {
+    /// Where a card at position `x` ends up after dealing, as `a * x + b`. Cuts of any size or sign are taken modulo
+    /// the deck length.
+    fn to_affine(&self, deck_length: &BigInt) -> Result<Affine, ShuffleError> {
+        let (a, b) = match self {
+            DealTechnique::NewStack => (BigInt::from(-1), BigInt::from(-1)),
+            DealTechnique::Cut(index) => (BigInt::one(), -index.mod_floor(deck_length)),
+            DealTechnique::Increment(inc) if !inc.gcd(deck_length).is_one() => {
+                return Err(ShuffleError::NonCoprimeIncrement {
+                    inc: inc.clone(),
+                    deck_length: deck_length.clone(),
+                })
             }
-        }
+            DealTechnique::Increment(inc) => (inc.clone(), BigInt::zero()),
+        };
+        Ok(Affine::new(a, b, deck_length))
     }
 }
 
@@ -77,142 +65,298 @@ fn modular_inverse(a: &BigInt, m: &BigInt) -> BigInt {
     (bezout_t(&r0, &r1, &t0, &t1) + m) % m
 }
 
-fn bad_bad_bubble_sort(
-    instructions: &Vec<DealTechnique>,
-    deck_length: &BigInt,
-) -> Vec<DealTechnique> {
-    // Input is only like 100 elements long at most, this is fine
-
-    // cut(a), inc(b) = inc(b), cut((a * b) % n)
-    // cut(a), new() = new(), cut(-a)
-    // inc(a), new() = new(), inc(a), cut(1 - a)
-
-    let mut output = instructions.to_vec();
-    'outer: loop {
-        for i in 0..output.len() - 1 {
-            match output[i].clone() {
-                DealTechnique::Cut(a) => match output[i + 1].clone() {
-                    DealTechnique::NewStack => {
-                        output[i] = DealTechnique::NewStack;
-                        output[i + 1] = DealTechnique::Cut(-a);
-
-                        continue 'outer;
-                    }
-                    DealTechnique::Increment(b) => {
-                        output[i] = DealTechnique::Increment(b.clone());
-                        output[i + 1] = DealTechnique::Cut((a * b) % deck_length);
-
-                        continue 'outer;
-                    }
-                    _ => (),
-                },
-                DealTechnique::Increment(a) => match output[i + 1].clone() {
-                    DealTechnique::NewStack => {
-                        output[i] = DealTechnique::NewStack;
-                        output[i + 1] = DealTechnique::Increment(a.clone());
-                        output.insert(i + 2, DealTechnique::Cut(BigInt::from(1) - a.clone()));
-
-                        continue 'outer;
-                    }
-                    _ => (),
-                },
-                _ => (),
-            };
+/// Modular linear function `x -> (a * x + b) % modulus`. Every deal technique is one of these, and so is any
+/// combination of them, which is what makes shuffling a deck a trillion times feasible.
+#[derive(Debug, Clone, PartialEq)]
+struct Affine {
+    a: BigInt,
+    b: BigInt,
+    modulus: BigInt,
+}
+
+impl Affine {
+    fn new(a: BigInt, b: BigInt, modulus: &BigInt) -> Affine {
+        Affine {
+            a: a.mod_floor(modulus),
+            b: b.mod_floor(modulus),
+            modulus: modulus.clone(),
         }
-        break;
-    }
-    output
-}
-
-fn compress(instructions: &Vec<DealTechnique>, deck_length: &BigInt) -> Vec<DealTechnique> {
-    // inc(a), inc(b) = inc((a * b) % n)
-    // cut(a), cut(b) = cut((a + b) % n)
-    // new(), new() = ()
-
-    let mut output = bad_bad_bubble_sort(&instructions, deck_length);
-
-    'outer: loop {
-        for i in 0..output.len() - 1 {
-            match &output[i] {
-                DealTechnique::Increment(a) => match &output[i + 1] {
-                    DealTechnique::Increment(b) => {
-                        output[i] = DealTechnique::Increment((a * b) % deck_length);
-                        output.remove(i + 1);
-                        continue 'outer;
-                    }
-                    _ => (),
-                },
-                DealTechnique::Cut(a) => match &output[i + 1] {
-                    DealTechnique::Cut(b) => {
-                        output[i] = DealTechnique::Cut((a + b) % deck_length);
-                        output.remove(i + 1);
-                        continue 'outer;
-                    }
-                    _ => (),
-                },
-                DealTechnique::NewStack => match output[i + 1] {
-                    DealTechnique::NewStack => {
-                        output.remove(i);
-                        output.remove(i);
-                        continue 'outer;
-                    }
-                    _ => (),
-                },
+    }
+
+    fn identity(modulus: &BigInt) -> Affine {
+        Affine::new(BigInt::one(), BigInt::zero(), modulus)
+    }
+
+    /// Applies `self` first, then `other`
+    fn compose(&self, other: &Affine) -> Affine {
+        Affine::new(
+            &other.a * &self.a,
+            &other.a * &self.b + &other.b,
+            &self.modulus,
+        )
+    }
+
+    fn invert(&self) -> Affine {
+        let a = modular_inverse(&self.a, &self.modulus);
+        let b = -(&a * &self.b);
+        Affine::new(a, b, &self.modulus)
+    }
+
+    /// Composes with itself `exp` times, by squaring
+    fn pow(&self, exp: &BigInt) -> Affine {
+        let mut result = Affine::identity(&self.modulus);
+        let mut square = self.clone();
+        let mut exp = exp.clone();
+        while exp > BigInt::zero() {
+            if exp.is_odd() {
+                result = result.compose(&square);
+            }
+            square = square.compose(&square);
+            exp /= 2;
+        }
+        result
+    }
+
+    fn apply(&self, x: &BigInt) -> BigInt {
+        (&self.a * x + &self.b).mod_floor(&self.modulus)
+    }
+}
+
+fn shuffle_transform(
+    instructions: &[DealTechnique],
+    deck_length: &BigInt,
+) -> Result<Affine, ShuffleError> {
+    instructions
+        .iter()
+        .try_fold(Affine::identity(deck_length), |transform, technique| {
+            Ok(transform.compose(&technique.to_affine(deck_length)?))
+        })
+}
+
+/// Applies the shuffle `times` times to a small deck and lays out the resulting order
+fn apply_n_small(
+    instructions: &[DealTechnique],
+    deck_length: usize,
+    times: usize,
+) -> Result<Vec<i64>, ShuffleError> {
+    let modulus = BigInt::from(deck_length);
+    let transform = shuffle_transform(instructions, &modulus)?.pow(&BigInt::from(times));
+    let mut deck = vec![0; deck_length];
+    for card in 0..deck_length {
+        let pos = transform.apply(&BigInt::from(card)).to_usize().unwrap();
+        deck[pos] = card as i64;
+    }
+    Ok(deck)
+}
+
+#[derive(Debug)]
+enum ParseError {
+    UnknownTechnique { line: usize },
+    InvalidNumber { line: usize },
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::UnknownTechnique { line } => {
+                write!(f, "Unknown technique on line {}", line + 1)
             }
+            ParseError::InvalidNumber { line } => write!(f, "Invalid number on line {}", line + 1),
         }
-        break;
     }
-    output
 }
 
-fn parse_input(filename: &str) -> Vec<DealTechnique> {
-    let input = std::fs::read_to_string(filename).unwrap();
+/// Whatever follows `technique` and a space on `line`, or an empty string if the number is missing altogether
+fn argument<'a>(line: &'a str, technique: &str) -> Option<&'a str> {
+    match line.strip_prefix(technique)? {
+        "" => Some(""),
+        rest => rest.strip_prefix(' '),
+    }
+}
+
+fn parse_techniques(src: &str) -> Result<Vec<DealTechnique>, ParseError> {
     let mut instructions = vec![];
-    for line in input.lines() {
-        if line.starts_with("cut") {
-            instructions.push(DealTechnique::Cut(line[4..].parse().unwrap()));
+    for (i, line) in src.lines().map(|line| line.trim()).enumerate() {
+        let number = |value: &str| {
+            value
+                .parse::<BigInt>()
+                .map_err(|_| ParseError::InvalidNumber { line: i })
+        };
+        if let Some(value) = argument(line, "cut") {
+            instructions.push(DealTechnique::Cut(number(value)?));
         } else if line.starts_with("deal into new stack") {
             instructions.push(DealTechnique::NewStack);
-        } else if line.starts_with("deal with increment") {
-            instructions.push(DealTechnique::Increment(line[20..].parse().unwrap()));
+        } else if let Some(value) = argument(line, "deal with increment") {
+            instructions.push(DealTechnique::Increment(number(value)?));
+        } else if !line.is_empty() {
+            return Err(ParseError::UnknownTechnique { line: i });
         }
     }
-    instructions
+    Ok(instructions)
+}
+
+fn parse_input(filename: &str) -> Result<Vec<DealTechnique>, ParseError> {
+    parse_techniques(&std::fs::read_to_string(filename).unwrap())
 }
 
 fn main() {
-    let instructions = parse_input("input");
+    let instructions = parse_input("input").unwrap_or_else(|e| panic!("Invalid input: {}", e));
 
     println!("22-1:");
-    let mut deck = (0..=10_006)
-        .map(|i| BigInt::from(i))
-        .collect::<Vec<BigInt>>();
-    for technique in &instructions {
-        deck = technique.apply(&deck);
-    }
-    println!(
-        "{}",
-        deck.iter().position(|c| *c == BigInt::from(2019)).unwrap()
-    );
+    let deck = apply_n_small(&instructions, 10_007, 1)
+        .unwrap_or_else(|e| panic!("Invalid shuffle: {}", e));
+    println!("{}", deck.iter().position(|card| *card == 2019).unwrap());
 
     println!("22-2:");
     let iterations = BigInt::from(101_741_582_076_661 as u64);
     let deck_length = BigInt::from(119_315_717_514_047 as u64);
-    let mut ending_card = BigInt::from(2020);
-
-    let mut remaining_iterations = iterations.clone();
-    while remaining_iterations > BigInt::from(0) {
-        let mut compressed_instructions = compress(&instructions, &deck_length).to_vec();
-        let mut iterations = BigInt::from(1);
-        while &iterations * 2 < remaining_iterations {
-            compressed_instructions.extend(compressed_instructions.to_vec());
-            compressed_instructions = compress(&compressed_instructions, &deck_length);
-            iterations *= 2;
+    // Work backwards from the final position to find which card ended up there
+    let transform = shuffle_transform(&instructions, &deck_length)
+        .unwrap_or_else(|e| panic!("Invalid shuffle: {}", e))
+        .pow(&iterations)
+        .invert();
+    println!("{}", transform.apply(&BigInt::from(2020)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new_stack_twice_is_identity() {
+        let deck = apply_n_small(&[DealTechnique::NewStack], 10, 2).unwrap();
+        assert_eq!(deck, (0..10).collect::<Vec<i64>>());
+        let deck = apply_n_small(&[DealTechnique::NewStack], 10, 1).unwrap();
+        assert_eq!(deck, (0..10).rev().collect::<Vec<i64>>());
+    }
+
+    #[test]
+    fn repeated_shuffle_matches_shuffling_again() {
+        let instructions = [
+            DealTechnique::Cut(BigInt::from(6)),
+            DealTechnique::Increment(BigInt::from(7)),
+            DealTechnique::NewStack,
+        ];
+        let once = apply_n_small(&instructions, 10, 1).unwrap();
+        assert_eq!(once, vec![3, 0, 7, 4, 1, 8, 5, 2, 9, 6]);
+        let twice = apply_n_small(&instructions, 10, 2).unwrap();
+        let by_hand = once
+            .iter()
+            .map(|card| once[*card as usize])
+            .collect::<Vec<_>>();
+        assert_eq!(twice, by_hand);
+    }
+
+    #[test]
+    fn affine_group_operations() {
+        let modulus = BigInt::from(11);
+        let affine = |a: i64, b: i64| Affine::new(BigInt::from(a), BigInt::from(b), &modulus);
+        let identity = Affine::identity(&modulus);
+        let transforms = (1..11)
+            .flat_map(|a| (0..11).step_by(3).map(move |b| (a, b)))
+            .map(|(a, b)| affine(a, b))
+            .collect::<Vec<_>>();
+        for f in &transforms {
+            assert_eq!(f.compose(&identity), *f);
+            assert_eq!(identity.compose(f), *f);
+            assert_eq!(f.compose(&f.invert()), identity);
+            assert_eq!(f.invert().compose(f), identity);
+            let g = affine(3, 5);
+            let h = affine(-2, 7);
+            assert_eq!(f.compose(&g).compose(&h), f.compose(&g.compose(&h)));
+            for x in 0..11 {
+                let x = BigInt::from(x);
+                assert_eq!(f.compose(&g).apply(&x), g.apply(&f.apply(&x)));
+            }
+            let repeated = (0..7).fold(identity.clone(), |acc, _| acc.compose(f));
+            assert_eq!(f.pow(&BigInt::from(7)), repeated);
+            assert_eq!(f.pow(&BigInt::zero()), identity);
         }
-        for technique in compressed_instructions.iter().rev() {
-            ending_card = technique.undo_position(&ending_card, &deck_length);
+        // A new stack undoes itself
+        let new_stack = DealTechnique::NewStack.to_affine(&modulus).unwrap();
+        assert_eq!(new_stack.compose(&new_stack), identity);
+    }
+
+    #[test]
+    fn cuts_wrap_and_increments_must_be_coprime() {
+        let cut = |n: i64| apply_n_small(&[DealTechnique::Cut(BigInt::from(n))], 10, 1).unwrap();
+        assert_eq!(cut(3), vec![3, 4, 5, 6, 7, 8, 9, 0, 1, 2]);
+        assert_eq!(cut(-4), vec![6, 7, 8, 9, 0, 1, 2, 3, 4, 5]);
+        assert_eq!(cut(13), cut(3));
+        assert_eq!(cut(-14), cut(-4));
+        assert_eq!(cut(-14), cut(6));
+        assert_eq!(cut(10), cut(0));
+
+        match apply_n_small(&[DealTechnique::Increment(BigInt::from(4))], 10, 1) {
+            Err(err @ ShuffleError::NonCoprimeIncrement { .. }) => {
+                assert_eq!(
+                    err.to_string(),
+                    "Increment 4 is not coprime with deck length 10"
+                );
+                let ShuffleError::NonCoprimeIncrement { inc, deck_length } = err;
+                assert_eq!((inc, deck_length), (BigInt::from(4), BigInt::from(10)));
+            }
+            Ok(deck) => panic!("Dealt {:?} with a shared factor", deck),
         }
-        remaining_iterations -= iterations.clone();
+        assert!(apply_n_small(&[DealTechnique::Increment(BigInt::from(3))], 10, 1).is_ok());
+    }
+
+    #[test]
+    fn published_examples() {
+        let examples = [
+            (
+                "deal with increment 7\ndeal into new stack\ndeal into new stack\n",
+                [0, 3, 6, 9, 2, 5, 8, 1, 4, 7],
+            ),
+            (
+                "cut 6\ndeal with increment 7\ndeal into new stack\n",
+                [3, 0, 7, 4, 1, 8, 5, 2, 9, 6],
+            ),
+            (
+                "deal with increment 7\ndeal with increment 9\ncut -2\n",
+                [6, 3, 0, 7, 4, 1, 8, 5, 2, 9],
+            ),
+            (
+                "deal into new stack\ncut -2\ndeal with increment 7\ncut 8\ncut -4\n\
+                 deal with increment 7\ncut 3\ndeal with increment 9\ndeal with increment 3\ncut -1\n",
+                [9, 2, 5, 8, 1, 4, 7, 0, 3, 6],
+            ),
+        ];
+        for (src, expected) in examples.iter() {
+            let instructions = parse_techniques(src).unwrap();
+            assert_eq!(
+                apply_n_small(&instructions, 10, 1).unwrap(),
+                expected.to_vec()
+            );
+        }
+    }
+
+    #[test]
+    fn bad_lines_are_reported() {
+        let error = |src| parse_techniques(src).unwrap_err();
+        assert!(matches!(
+            error("cut"),
+            ParseError::InvalidNumber { line: 0 }
+        ));
+        assert!(matches!(
+            error("cut "),
+            ParseError::InvalidNumber { line: 0 }
+        ));
+        assert!(matches!(
+            error("cut 1\ndeal with increment"),
+            ParseError::InvalidNumber { line: 1 }
+        ));
+        assert!(matches!(
+            error("deal with increment seven"),
+            ParseError::InvalidNumber { line: 0 }
+        ));
+        assert!(matches!(
+            error("cut 1\n\ncutting 3"),
+            ParseError::UnknownTechnique { line: 2 }
+        ));
+        assert!(matches!(
+            error("shuffle"),
+            ParseError::UnknownTechnique { line: 0 }
+        ));
     }
-    println!("{}", ending_card);
 }
@@ -21,73 +21,216 @@ impl Packet {
     }
 }
 
-fn run_network(nic: &Vec<i64>, early_return: bool) -> i64 {
-    let mut network = vec![];
-    let mut packet_queue: HashMap<usize, VecDeque<(i64, i64)>> = HashMap::new();
-    let mut nat_packet: Option<(i64, i64)> = None;
-    let mut last_sent_nat_packet: Option<(i64, i64)> = None;
-    for i in 0..50 {
-        network.push((intcode::Cpu::new(nic.to_vec()), Packet::new()));
-        packet_queue.insert(i, VecDeque::new());
-        // Initialize network address
-        network[i].0.push(&vec![i as i64]);
+#[derive(Debug)]
+enum NetworkError {
+    Halted(usize),
+    Fault(usize, intcode::DecodeError),
+    UnknownAddress(usize, i64),
+}
+
+impl std::fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NetworkError::Halted(address) => write!(f, "NIC {} halted", address),
+            NetworkError::Fault(address, err) => write!(f, "NIC {} faulted: {}", address, err),
+            NetworkError::UnknownAddress(address, to) => {
+                write!(f, "NIC {} sent a packet to unknown address {}", address, to)
+            }
+        }
     }
-    let mut idle_count = 0;
-    loop {
-        if idle_count == network.len() && nat_packet.is_some() {
-            if let Some((_, y)) = last_sent_nat_packet {
-                if y == nat_packet.unwrap().1 {
-                    return y;
+}
+
+struct Network {
+    nics: Vec<(intcode::Cpu, Packet)>,
+    packet_queue: HashMap<usize, VecDeque<(i64, i64)>>,
+    // A NIC is idle once it has asked for input with no packets waiting, until it receives or sends one
+    idle: Vec<bool>,
+    nat_packet: Option<(i64, i64)>,
+    last_sent_nat_packet: Option<(i64, i64)>,
+}
+
+impl Network {
+    /// Boots one NIC per rom, each given its matching boot value (normally its own address) as its first input
+    fn new(roms: &[Vec<i64>], boot_values: &[i64]) -> Network {
+        assert_eq!(
+            roms.len(),
+            boot_values.len(),
+            "Every NIC needs a boot value"
+        );
+        let mut nics = vec![];
+        let mut packet_queue = HashMap::new();
+        for (i, (rom, boot_value)) in roms.iter().zip(boot_values).enumerate() {
+            let mut cpu = intcode::Cpu::new(rom.to_vec());
+            cpu.push(&vec![*boot_value]);
+            nics.push((cpu, Packet::new()));
+            packet_queue.insert(i, VecDeque::new());
+        }
+        Network {
+            idle: vec![false; nics.len()],
+            nics,
+            packet_queue,
+            nat_packet: None,
+            last_sent_nat_packet: None,
+        }
+    }
+
+    /// Queues a packet for delivery to `address` next time it's polled
+    fn send(&mut self, address: usize, packet: (i64, i64)) {
+        self.packet_queue
+            .get_mut(&address)
+            .unwrap()
+            .push_back(packet);
+    }
+
+    /// Polls every NIC once, returning a Y value once the puzzle's stopping condition is met. NICs are meant to run
+    /// forever, so one halting or faulting is an error rather than something to keep polling.
+    fn step(&mut self, early_return: bool) -> Result<Option<i64>, NetworkError> {
+        let network_idle = self.idle.iter().all(|i| *i)
+            && self.packet_queue.values().all(|queue| queue.is_empty());
+        if network_idle && self.nat_packet.is_some() {
+            if let Some((_, y)) = self.last_sent_nat_packet {
+                if y == self.nat_packet.unwrap().1 {
+                    return Ok(Some(y));
                 }
             }
-            packet_queue
-                .get_mut(&0)
-                .unwrap()
-                .push_back(nat_packet.unwrap());
-            last_sent_nat_packet = nat_packet;
-            nat_packet = None;
+            self.send(0, self.nat_packet.unwrap());
+            self.last_sent_nat_packet = self.nat_packet;
+            self.nat_packet = None;
         }
 
-        idle_count = 0;
-        for (i, (cpu, next_packet)) in network.iter_mut().enumerate() {
-            let queue = &mut packet_queue.get_mut(&i).unwrap();
+        for (i, (cpu, next_packet)) in self.nics.iter_mut().enumerate() {
+            let queue = &mut self.packet_queue.get_mut(&i).unwrap();
+            if !queue.is_empty() {
+                self.idle[i] = false;
+            }
             for (x, y) in queue.iter() {
                 cpu.push(&vec![*x, *y]);
             }
             queue.clear();
-            if cpu.inputs.len() == 0 {
-                cpu.inputs.push_back(-1);
-            }
-            if cpu.inputs.len() == 1 && cpu.inputs[0] == -1 {
-                idle_count += 1;
+            if cpu.is_waiting_for_input() {
+                cpu.push(&vec![-1]);
+                self.idle[i] = true;
             }
 
             match cpu.poll() {
-                intcode::Poll::Result(i) => {
-                    if let Some((a, x, y)) = next_packet.push(i) {
+                intcode::Poll::Result(value) => {
+                    self.idle[i] = false;
+                    if let Some((a, x, y)) = next_packet.push(value) {
                         if a == 255 {
                             if early_return {
-                                return y;
+                                return Ok(Some(y));
                             }
-                            nat_packet = Some((x, y));
+                            self.nat_packet = Some((x, y));
                         } else {
-                            packet_queue
-                                .get_mut(&(a as usize))
-                                .unwrap()
-                                .push_back((x, y));
+                            match self.packet_queue.get_mut(&(a as usize)) {
+                                Some(queue) if a >= 0 => queue.push_back((x, y)),
+                                _ => return Err(NetworkError::UnknownAddress(i, a)),
+                            }
                         }
                         *next_packet = Packet::new();
                     }
                 }
-                _ => {}
+                intcode::Poll::Stop => return Err(NetworkError::Halted(i)),
+                intcode::Poll::Fault(err) => return Err(NetworkError::Fault(i, err)),
+                intcode::Poll::NeedInput | intcode::Poll::None => {}
+            }
+        }
+        Ok(None)
+    }
+
+    fn run(&mut self, early_return: bool) -> Result<i64, NetworkError> {
+        loop {
+            if let Some(y) = self.step(early_return)? {
+                return Ok(y);
             }
         }
     }
 }
 
+fn run_network(nic: &Vec<i64>, early_return: bool) -> i64 {
+    let boot_values = (0..50).collect::<Vec<i64>>();
+    Network::new(&vec![nic.to_vec(); 50], &boot_values)
+        .run(early_return)
+        .unwrap_or_else(|e| panic!("Network failure: {}", e))
+}
+
 fn main() {
     let rom = intcode::parse_rom("input");
 
     println!("23-1:\n{}", run_network(&rom, true));
     println!("23-2:\n{}", run_network(&rom, false));
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn idle_nic_reads_one_empty_packet_per_request() {
+        // Adds every value read into [100] and counts the reads in [101]
+        let rom = vec![
+            3, 99, 3, 102, 1, 100, 102, 100, 1001, 101, 1, 101, 1105, 1, 2,
+        ];
+        let mut network = Network::new(std::slice::from_ref(&rom), &[0]);
+        // Boot, then ten passes through the four op loop
+        for _ in 0..41 {
+            assert_eq!(network.step(false).unwrap(), None);
+            assert!(network.nics[0].0.pending_inputs().is_empty());
+        }
+        assert!(network.idle[0]);
+        let mut expected = rom;
+        expected.resize(103, 0);
+        expected[100] = -10;
+        expected[101] = 10;
+        expected[102] = -1;
+        assert!(network.nics[0].0.memory_eq(&expected));
+    }
+
+    #[test]
+    fn packets_are_delivered_intact() {
+        // Sends (42, 7) to NIC 1 then spins forever
+        let sender = vec![3, 100, 104, 1, 104, 42, 104, 7, 1105, 1, 8];
+        // Waits for a packet and forwards it to the NAT
+        let receiver = vec![
+            3, 100, 3, 101, 1008, 101, -1, 103, 1005, 103, 2, 3, 102, 104, 255, 4, 101, 4, 102,
+            1105, 1, 19,
+        ];
+        let mut network = Network::new(&[sender, receiver], &[0, 1]);
+        for _ in 0..20 {
+            assert_eq!(network.step(false).unwrap(), None);
+        }
+        assert_eq!(network.nat_packet, Some((42, 7)));
+    }
+
+    #[test]
+    fn packets_to_unknown_addresses_are_errors() {
+        for address in [2, 254, 256, -1].iter() {
+            let rom = vec![3, 100, 104, *address, 104, 42, 104, 7, 1105, 1, 8];
+            let mut network = Network::new(&[rom], &[0]);
+            match network.run(false) {
+                Err(NetworkError::UnknownAddress(0, to)) => assert_eq!(to, *address),
+                Err(err) => panic!("Unexpected error: {}", err),
+                Ok(y) => panic!("Unexpected result: {}", y),
+            }
+        }
+    }
+
+    #[test]
+    fn halting_and_faulting_nics_are_reported() {
+        let spinner = vec![1105, 1, 0];
+        let mut network = Network::new(&[spinner.clone(), vec![99]], &[0, 1]);
+        match network.run(false) {
+            Err(NetworkError::Halted(1)) => {}
+            Err(err) => panic!("Unexpected error: {}", err),
+            Ok(y) => panic!("Unexpected result: {}", y),
+        }
+        let mut network = Network::new(&[spinner, vec![42]], &[0, 1]);
+        match network.run(false) {
+            Err(err @ NetworkError::Fault(1, _)) => {
+                assert!(err.to_string().starts_with("NIC 1 faulted: "))
+            }
+            Err(err) => panic!("Unexpected error: {}", err),
+            Ok(y) => panic!("Unexpected result: {}", y),
+        }
+    }
+}
@@ -1,26 +1,66 @@
 use std::cmp::{max, min};
 use std::collections::{HashMap, HashSet};
 
+#[derive(Debug)]
+enum ParseError {
+    RaggedRow {
+        row: usize,
+        width: usize,
+        expected: usize,
+    },
+    UnexpectedChar {
+        row: usize,
+        c: char,
+    },
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::RaggedRow {
+                row,
+                width,
+                expected,
+            } => write!(f, "Row {} is {} wide, expected {}", row, width, expected),
+            ParseError::UnexpectedChar { row, c } => {
+                write!(f, "Row {} has unexpected tile '{}'", row, c)
+            }
+        }
+    }
+}
+
 fn parse_input(filename: &str) -> BugGrid {
     let input = std::fs::read_to_string(filename).unwrap();
+    parse_str(&input).unwrap_or_else(|e| panic!("Invalid input: {}", e))
+}
+
+fn parse_str(input: &str) -> Result<BugGrid, ParseError> {
     let mut tiles = HashMap::new();
-    let width = input.lines().next().unwrap().trim().len();
+    let width = input.lines().next().map_or(0, |line| line.trim().len());
     let height = input.lines().count();
     for (y, line) in input.lines().enumerate() {
+        let line = line.trim();
+        if line.len() != width {
+            return Err(ParseError::RaggedRow {
+                row: y,
+                width: line.len(),
+                expected: width,
+            });
+        }
         for (x, b) in line.chars().enumerate() {
             let bug = match b {
                 '#' => true,
                 '.' => false,
-                _ => panic!("Unexpected input"),
+                c => return Err(ParseError::UnexpectedChar { row: y, c }),
             };
             tiles.insert((x as i32, y as i32), bug);
         }
     }
-    BugGrid {
+    Ok(BugGrid {
         tiles,
         width: width as i32,
         height: height as i32,
-    }
+    })
 }
 
 #[derive(Debug, Clone)]
@@ -31,6 +71,31 @@ struct BugGrid {
 }
 
 impl BugGrid {
+    /// Places or removes a single bug. There's nothing to invalidate, `tick` always reads the whole grid.
+    #[allow(dead_code)]
+    fn set(&mut self, x: i32, y: i32, bug: bool) {
+        assert!(
+            self.tiles.contains_key(&(x, y)),
+            "({}, {}) is outside the grid",
+            x,
+            y
+        );
+        self.tiles.insert((x, y), bug);
+    }
+
+    /// Every bug in reading order
+    #[allow(dead_code)]
+    fn bug_positions(&self) -> Vec<(i32, i32)> {
+        let mut bugs = self
+            .tiles
+            .iter()
+            .filter(|(_, bug)| **bug)
+            .map(|(pos, _)| *pos)
+            .collect::<Vec<_>>();
+        bugs.sort_by_key(|(x, y)| (*y, *x));
+        bugs
+    }
+
     fn tick(&self) -> BugGrid {
         let mut tiles = HashMap::new();
 
@@ -75,6 +140,76 @@ impl BugGrid {
 
         score
     }
+
+    /// Inverse of `score`, each bit is a tile in reading order
+    fn from_score(score: u32, width: i32, height: i32) -> BugGrid {
+        let mut tiles = HashMap::new();
+        for y in 0..height {
+            for x in 0..width {
+                tiles.insert((x, y), score & (1 << (y * width + x)) != 0);
+            }
+        }
+        BugGrid {
+            tiles,
+            width,
+            height,
+        }
+    }
+}
+
+/// Biodiversity rating of a layout given as tiles in reading order
+#[cfg(test)]
+fn biodiversity(layout: &[bool]) -> u32 {
+    layout
+        .iter()
+        .enumerate()
+        .filter(|(_, bug)| **bug)
+        .fold(0, |score, (i, _)| score | 1 << i)
+}
+
+const GRID_SIZE: u32 = 5;
+
+/// For each tile of a 5x5 grid, the bits of its up to four neighbours in `score` order
+const fn neighbour_masks() -> [u32; 25] {
+    let mut masks = [0; 25];
+    let mut i = 0;
+    while i < GRID_SIZE * GRID_SIZE {
+        let (x, y) = (i % GRID_SIZE, i / GRID_SIZE);
+        let mut mask = 0;
+        if x > 0 {
+            mask |= 1 << (i - 1);
+        }
+        if x < GRID_SIZE - 1 {
+            mask |= 1 << (i + 1);
+        }
+        if y > 0 {
+            mask |= 1 << (i - GRID_SIZE);
+        }
+        if y < GRID_SIZE - 1 {
+            mask |= 1 << (i + GRID_SIZE);
+        }
+        masks[i as usize] = mask;
+        i += 1;
+    }
+    masks
+}
+
+const NEIGHBOUR_MASKS: [u32; 25] = neighbour_masks();
+
+/// Same rules as `BugGrid::tick` for a 5x5 grid, working directly on its biodiversity score
+fn next_generation(state: u32) -> u32 {
+    NEIGHBOUR_MASKS
+        .iter()
+        .enumerate()
+        .fold(0, |next, (i, mask)| {
+            let bug = state & (1 << i) != 0;
+            let adjacent_bugs = (state & mask).count_ones();
+            if adjacent_bugs == 1 || (!bug && adjacent_bugs == 2) {
+                next | 1 << i
+            } else {
+                next
+            }
+        })
 }
 
 impl std::fmt::Display for BugGrid {
@@ -162,6 +297,46 @@ impl RecursiveBugGrid {
     fn tick(&self) -> RecursiveBugGrid {
         let mut layers = self.layers.clone();
 
+        for (depth, layer) in &mut layers {
+            // A layer with no bugs in or around it can't change this minute
+            if self.is_quiet(*depth) {
+                continue;
+            }
+            for ((x, y), _) in layer.clone() {
+                if self.center() == (x, y) {
+                    continue;
+                }
+                layer.insert((x, y), self.next_tile(*depth, x, y));
+            }
+        }
+
+        // Create new layers only once bugs reach the cells bordering them
+        let (min_depth, max_depth) = layers
+            .keys()
+            .fold((0, 0), |(mi, ma), depth| (min(mi, *depth), max(ma, *depth)));
+        if self.any_bugs(&layers[&min_depth], |adjacency| {
+            matches!(adjacency, LayerAdjacency::Outer)
+        }) {
+            layers.entry(min_depth - 1).or_insert(self.new_layer());
+        }
+        if self.any_bugs(&layers[&max_depth], |adjacency| {
+            matches!(adjacency, LayerAdjacency::Inner(_))
+        }) {
+            layers.entry(max_depth + 1).or_insert(self.new_layer());
+        }
+
+        RecursiveBugGrid {
+            layers,
+            width: self.width,
+            height: self.height,
+        }
+    }
+
+    /// Reference implementation of `tick` that recomputes every layer
+    #[cfg(test)]
+    fn tick_naive(&self) -> RecursiveBugGrid {
+        let mut layers = self.layers.clone();
+
         for (depth, layer) in &mut layers {
             for ((x, y), _) in layer.clone() {
                 if self.center() == (x, y) {
@@ -189,6 +364,33 @@ impl RecursiveBugGrid {
         }
     }
 
+    fn any_bugs(
+        &self,
+        layer: &HashMap<(i32, i32), bool>,
+        relevant: impl Fn(LayerAdjacency) -> bool,
+    ) -> bool {
+        layer
+            .iter()
+            .any(|((x, y), bug)| *bug && relevant(self.adjacency(*x, *y)))
+    }
+
+    /// True if no tile on `depth` can change, i.e. the layer is empty and so are
+    /// the cells of the outer layer around its center and the border of the inner layer
+    fn is_quiet(&self, depth: i32) -> bool {
+        let empty = |depth: i32, relevant: fn(LayerAdjacency) -> bool| match self.layers.get(&depth)
+        {
+            Some(layer) => !self.any_bugs(layer, relevant),
+            None => true,
+        };
+        empty(depth, |_| true)
+            && empty(depth - 1, |adjacency| {
+                matches!(adjacency, LayerAdjacency::Inner(_))
+            })
+            && empty(depth + 1, |adjacency| {
+                matches!(adjacency, LayerAdjacency::Outer)
+            })
+    }
+
     fn count_tile(&self, depth: i32, from_x: i32, from_y: i32, x: i32, y: i32) -> i32 {
         let center = self.center();
         if x < 0 {
@@ -264,23 +466,30 @@ impl RecursiveBugGrid {
 
         score
     }
+
+    /// Bugs on a single layer, 0 for layers the simulation hasn't reached yet
+    #[allow(dead_code)]
+    fn bugs_at_depth(&self, depth: i32) -> usize {
+        self.layers
+            .get(&depth)
+            .map_or(0, |layer| layer.values().filter(|bug| **bug).count())
+    }
 }
 
 fn main() {
     let input = parse_input("input");
 
     println!("24-1:");
+    let next = |score| match (input.width, input.height) {
+        (5, 5) => next_generation(score),
+        (width, height) => BugGrid::from_score(score, width, height).tick().score(),
+    };
     let mut previous_scores = HashSet::<u32>::new();
-    let mut grid = input.clone();
-    loop {
-        let score = grid.score();
-        if previous_scores.contains(&score) {
-            println!("{}", score);
-            break;
-        }
-        previous_scores.insert(score);
-        grid = grid.tick();
+    let mut score = input.score();
+    while previous_scores.insert(score) {
+        score = next(score);
     }
+    println!("{}", score);
 
     println!("24-2:");
     let mut grid = RecursiveBugGrid::from(&input);
@@ -289,3 +498,159 @@ fn main() {
     }
     println!("{}", grid.score());
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "....#
+#..#.
+#..##
+..#..
+#....
+";
+
+    #[test]
+    fn parses_clean_grid() {
+        let grid = parse_str(EXAMPLE).unwrap();
+        assert_eq!((grid.width, grid.height), (5, 5));
+        assert_eq!(grid.tiles.len(), 25);
+        assert!(grid.tiles[&(4, 0)]);
+        assert!(!grid.tiles[&(0, 0)]);
+    }
+
+    #[test]
+    fn rejects_ragged_and_unknown_rows() {
+        assert!(matches!(
+            parse_str("....#\n#..#\n#..##\n"),
+            Err(ParseError::RaggedRow {
+                row: 1,
+                width: 4,
+                expected: 5
+            })
+        ));
+        assert!(matches!(
+            parse_str("....#\n#..?.\n"),
+            Err(ParseError::UnexpectedChar { row: 1, c: '?' })
+        ));
+    }
+
+    #[test]
+    fn scores_round_trip() {
+        let repeated = parse_str(".....\n.....\n.....\n#....\n.#...\n").unwrap();
+        assert_eq!(repeated.score(), 2129920);
+        let mut seed: u32 = 0x2019_1224;
+        for _ in 0..50 {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            let score = seed & ((1 << 25) - 1);
+            let grid = BugGrid::from_score(score, 5, 5);
+            assert_eq!(grid.score(), score);
+            let layout = (0..5)
+                .flat_map(|y| (0..5).map(move |x| (x, y)))
+                .map(|pos| grid.tiles[&pos])
+                .collect::<Vec<bool>>();
+            assert_eq!(biodiversity(&layout), score);
+            assert_eq!(parse_str(&grid.to_string()).unwrap().score(), score);
+        }
+    }
+
+    #[test]
+    fn tick_matches_naive_tick() {
+        let start = RecursiveBugGrid::from(&parse_str(EXAMPLE).unwrap());
+        let mut fast = start.clone();
+        let mut naive = start;
+        for minute in 1..=50 {
+            fast = fast.tick();
+            naive = naive.tick_naive();
+            // The naive tick keeps empty outer layers that the fast one never creates
+            for (depth, layer) in &naive.layers {
+                let fast_layer = fast.layers.get(depth);
+                for (pos, bug) in layer {
+                    let fast_bug = fast_layer.is_some_and(|tiles| tiles[pos]);
+                    assert_eq!(
+                        fast_bug, *bug,
+                        "Minute {} depth {} tile {:?}",
+                        minute, depth, pos
+                    );
+                }
+            }
+            assert!(fast
+                .layers
+                .keys()
+                .all(|depth| naive.layers.contains_key(depth)));
+            if minute == 10 {
+                assert_eq!(fast.score(), 99);
+            }
+        }
+    }
+
+    #[test]
+    fn set_cell_then_tick() {
+        let mut grid = parse_str(".....\n.....\n.....\n.....\n.....\n").unwrap();
+        assert!(grid.bug_positions().is_empty());
+        grid.set(2, 2, true);
+        grid.set(4, 0, true);
+        assert_eq!(grid.bug_positions(), vec![(4, 0), (2, 2)]);
+        let next = grid.tick();
+        // A lone bug dies while each empty neighbour next to exactly one bug gets infested
+        assert!(!next.tiles[&(2, 2)]);
+        assert!(next.tiles[&(2, 1)]);
+        assert!(next.tiles[&(3, 0)]);
+        assert_eq!(
+            next.bug_positions(),
+            vec![(3, 0), (2, 1), (4, 1), (1, 2), (3, 2), (2, 3)]
+        );
+        grid.set(4, 0, false);
+        assert_eq!(grid.bug_positions(), vec![(2, 2)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "(5, 0) is outside the grid")]
+    fn set_outside_the_grid() {
+        parse_str(EXAMPLE).unwrap().set(5, 0, true);
+    }
+
+    #[test]
+    fn next_generation_matches_tick() {
+        // The centre tile touches the middle of each side
+        assert_eq!(NEIGHBOUR_MASKS[12], 1 << 7 | 1 << 11 | 1 << 13 | 1 << 17);
+        assert_eq!(NEIGHBOUR_MASKS[0], 1 << 1 | 1 << 5);
+        assert_eq!(NEIGHBOUR_MASKS[24], 1 << 19 | 1 << 23);
+
+        let mut grid = parse_str(EXAMPLE).unwrap();
+        let mut state = grid.score();
+        for _ in 0..50 {
+            grid = grid.tick();
+            state = next_generation(state);
+            assert_eq!(state, grid.score());
+        }
+    }
+
+    #[test]
+    fn bugs_spread_one_layer_per_tick() {
+        // A single bug right above the centre touches the whole top row of the layer inside it
+        let grid = parse_str(".....\n..#..\n.....\n.....\n.....\n").unwrap();
+        let grid = RecursiveBugGrid::from(&grid);
+        assert_eq!(grid.bugs_at_depth(0), 1);
+        assert_eq!(grid.bugs_at_depth(1), 0);
+        let grid = grid.tick();
+        assert_eq!(grid.bugs_at_depth(-1), 0);
+        assert_eq!(grid.bugs_at_depth(0), 3);
+        assert_eq!(grid.bugs_at_depth(1), 5);
+        assert_eq!(grid.bugs_at_depth(2), 0);
+        assert_eq!(grid.bugs_at_depth(40), 0);
+
+        let mut grid = RecursiveBugGrid::from(&parse_str(EXAMPLE).unwrap());
+        for _ in 0..10 {
+            grid = grid.tick();
+        }
+        let per_layer = (-5..=5)
+            .map(|depth| grid.bugs_at_depth(depth))
+            .collect::<Vec<_>>();
+        assert!(per_layer.iter().all(|bugs| *bugs > 0));
+        assert_eq!(per_layer.iter().sum::<usize>(), 99);
+        assert_eq!(grid.bugs_at_depth(-6) + grid.bugs_at_depth(6), 0);
+    }
+}
@@ -1,9 +1,175 @@
 extern crate intcode;
 
+use std::collections::{HashMap, VecDeque};
+
 fn str_to_input(input: &str) -> Vec<i64> {
     input.bytes().map(|b| b as i64).collect()
 }
 
+/// Anything chattier than this is assumed to be stuck printing forever (looking at you, infinite loop)
+const OUTPUT_LIMIT: usize = 10_000;
+
+/// Runs a single command, returning its output or `None` if the droid didn't make it back to a prompt
+fn run_command(cpu: &mut intcode::Cpu, command: &str) -> Option<String> {
+    let mut output = String::new();
+    cpu.push(&str_to_input(command));
+    loop {
+        match cpu.poll() {
+            intcode::Poll::Result(c) => {
+                output.push(c as u8 as char);
+                if output.len() > OUTPUT_LIMIT {
+                    return None;
+                }
+            }
+            intcode::Poll::NeedInput => return Some(output),
+            intcode::Poll::Stop => return None,
+            intcode::Poll::Fault(err) => panic!("{}", err),
+            intcode::Poll::None => (),
+        }
+    }
+}
+
+/// Death is either the program ending, or being stuck where you stand forever
+fn is_fatal(output: &Option<String>) -> bool {
+    match output {
+        Some(output) => output.contains("You can't move"),
+        None => true,
+    }
+}
+
+/// Tries each item on a throwaway copy of the droid first, only picking up the ones that don't kill it
+#[allow(dead_code)]
+fn safe_items(cpu: &mut intcode::Cpu, items: &[&str]) -> Vec<String> {
+    let mut safe = vec![];
+    for item in items {
+        let take = format!("take {}\n", item);
+        let mut probe = cpu.clone();
+        let output = run_command(&mut probe, &take).and_then(|_| run_command(&mut probe, "inv\n"));
+        if !is_fatal(&output) {
+            run_command(cpu, &take);
+            safe.push(item.to_string());
+        }
+    }
+    safe
+}
+
+#[derive(Debug, PartialEq)]
+struct Room {
+    name: String,
+    doors: Vec<String>,
+    items: Vec<String>,
+}
+
+/// Parses the room the droid ends up in. Being ejected from a room prints two descriptions, so the last one wins.
+#[allow(dead_code)]
+fn parse_room(output: &str) -> Option<Room> {
+    let start = output.rfind("== ")?;
+    let mut lines = output[start..].lines();
+    let name = lines
+        .next()?
+        .trim_start_matches("== ")
+        .trim_end_matches(" ==")
+        .to_string();
+    let mut room = Room {
+        name,
+        doors: vec![],
+        items: vec![],
+    };
+    let mut section = None;
+    for line in lines {
+        match line {
+            "Doors here lead:" => section = Some(&mut room.doors),
+            "Items here:" => section = Some(&mut room.items),
+            _ if line.starts_with("- ") => {
+                if let Some(list) = section.as_mut() {
+                    list.push(line[2..].to_string());
+                }
+            }
+            _ => section = None,
+        }
+    }
+    Some(room)
+}
+
+fn opposite(direction: &str) -> &'static str {
+    match direction {
+        "north" => "south",
+        "south" => "north",
+        "east" => "west",
+        "west" => "east",
+        _ => panic!("Unknown direction {}", direction),
+    }
+}
+
+/// Rooms of the ship and the doors between them
+#[derive(Debug, Default)]
+struct ShipMap {
+    doors: HashMap<String, HashMap<String, String>>,
+}
+
+#[allow(dead_code)]
+impl ShipMap {
+    /// Records that going `direction` from `from` leads to `to`, and so the opposite way leads back
+    fn connect(&mut self, from: &str, direction: &str, to: &str) {
+        self.doors
+            .entry(from.to_string())
+            .or_default()
+            .insert(direction.to_string(), to.to_string());
+        self.doors
+            .entry(to.to_string())
+            .or_default()
+            .insert(opposite(direction).to_string(), from.to_string());
+    }
+
+    /// Directions to take for the fewest moves from one room to another, if they're connected
+    fn route(&self, from: &str, to: &str) -> Option<Vec<String>> {
+        let mut previous: HashMap<&str, (&str, &str)> = HashMap::new();
+        let mut queue = VecDeque::new();
+        queue.push_back(from);
+        while let Some(room) = queue.pop_front() {
+            if room == to {
+                let mut route = vec![];
+                let mut room = room;
+                while let Some((last_room, direction)) = previous.get(room) {
+                    route.push(direction.to_string());
+                    room = last_room;
+                }
+                route.reverse();
+                return Some(route);
+            }
+            // Sorted so equally short routes are always picked the same way
+            let mut doors = self
+                .doors
+                .get(room)
+                .map(|doors| doors.iter().collect::<Vec<_>>())
+                .unwrap_or_default();
+            doors.sort();
+            for (direction, next_room) in doors {
+                if next_room != from && !previous.contains_key(next_room.as_str()) {
+                    previous.insert(next_room, (room, direction));
+                    queue.push_back(next_room);
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Moves the droid from room `from` to `target`, returning the room it ends up in. Gives `None` if there's no known way
+/// there, the droid didn't survive the trip, or it was already there, since standing still prints nothing to parse.
+#[allow(dead_code)]
+fn walk_to(cpu: &mut intcode::Cpu, map: &ShipMap, from: &str, target: &str) -> Option<Room> {
+    let mut room = None;
+    for direction in map.route(from, target)? {
+        let output = run_command(cpu, &format!("{}\n", direction));
+        if is_fatal(&output) {
+            return None;
+        }
+        room = parse_room(&output.unwrap());
+    }
+    room
+}
+
 #[allow(dead_code)]
 fn run_interactive(rom: &Vec<i64>, initial_input: &str) {
     let mut cpu = intcode::Cpu::new(rom.to_vec());
@@ -13,7 +179,7 @@ fn run_interactive(rom: &Vec<i64>, initial_input: &str) {
             c @ Some(0..=255) => {
                 let c = c.unwrap() as u8 as char;
                 print!("{}", c);
-                if c == '?' && cpu.inputs.len() == 0 {
+                if c == '?' && !cpu.has_pending_input() {
                     print!("\n");
                     let mut input = String::new();
                     std::io::stdin()
@@ -66,3 +232,103 @@ fn main() {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn death_messages_are_fatal() {
+        let magnet = "\nYou take the giant electromagnet.\n\nCommand?\n\nThe giant electromagnet is stuck to you.  You can't move!!\n\nCommand?\n";
+        let fine = "\nYou take the space heater.\n\nCommand?\n";
+        assert!(is_fatal(&Some(magnet.to_string())));
+        assert!(!is_fatal(&Some(fine.to_string())));
+        // Being eaten by a grue ends the program, so the droid never gets back to a prompt
+        let mut cpu = intcode::Cpu::new(intcode::parse_rom("input"));
+        run_command(&mut cpu, "");
+        run_command(&mut cpu, "south\n");
+        let output = run_command(&mut cpu, "take photons\n");
+        assert!(output.is_none());
+        assert!(is_fatal(&output));
+    }
+
+    #[test]
+    fn safe_items_leaves_lethal_items_behind() {
+        let mut cpu = intcode::Cpu::new(intcode::parse_rom("input"));
+        run_command(&mut cpu, "");
+        run_command(&mut cpu, "south\n");
+        assert!(safe_items(&mut cpu, &["photons"]).is_empty());
+        run_command(&mut cpu, "east\n");
+        assert_eq!(safe_items(&mut cpu, &["space heater"]), vec!["space heater"]);
+        let inventory = run_command(&mut cpu, "inv\n").unwrap();
+        assert!(inventory.contains("- space heater"));
+        assert!(!inventory.contains("- photons"));
+    }
+
+    #[test]
+    fn parses_rooms() {
+        let mut cpu = intcode::Cpu::new(intcode::parse_rom("input"));
+        let room = parse_room(&run_command(&mut cpu, "").unwrap()).unwrap();
+        assert_eq!(room.name, "Hull Breach");
+        assert_eq!(room.doors, vec!["south", "west"]);
+        assert!(room.items.is_empty());
+
+        let room = parse_room(&run_command(&mut cpu, "south\n").unwrap()).unwrap();
+        assert_eq!(
+            room,
+            Room {
+                name: "Sick Bay".to_string(),
+                doors: vec!["north".to_string(), "east".to_string(), "west".to_string()],
+                items: vec!["photons".to_string()],
+            }
+        );
+
+        let room = parse_room(&run_command(&mut cpu, "east\n").unwrap()).unwrap();
+        assert_eq!(room.name, "Warp Drive Maintenance");
+        assert_eq!(room.doors, vec!["west"]);
+        assert_eq!(room.items, vec!["space heater"]);
+
+        assert_eq!(parse_room(&run_command(&mut cpu, "take space heater\n").unwrap()), None);
+    }
+
+    #[test]
+    fn routes_over_a_mocked_map() {
+        let mut map = ShipMap::default();
+        map.connect("Hull Breach", "south", "Sick Bay");
+        map.connect("Sick Bay", "east", "Warp Drive Maintenance");
+        map.connect("Sick Bay", "west", "Kitchen");
+        map.connect("Kitchen", "north", "Arcade");
+        map.connect("Hull Breach", "west", "Arcade");
+        assert_eq!(
+            map.route("Warp Drive Maintenance", "Arcade").unwrap(),
+            vec!["west", "north", "west"]
+        );
+        // Both ways round the loop are two moves, so the doors are tried in name order
+        assert_eq!(
+            map.route("Arcade", "Sick Bay").unwrap(),
+            vec!["east", "south"]
+        );
+        assert_eq!(
+            map.route("Kitchen", "Hull Breach").unwrap(),
+            vec!["east", "north"]
+        );
+        assert!(map.route("Arcade", "Arcade").unwrap().is_empty());
+        assert_eq!(map.route("Arcade", "Observatory"), None);
+    }
+
+    #[test]
+    fn walks_the_droid_between_rooms() {
+        let mut map = ShipMap::default();
+        map.connect("Hull Breach", "south", "Sick Bay");
+        map.connect("Sick Bay", "east", "Warp Drive Maintenance");
+        let mut cpu = intcode::Cpu::new(intcode::parse_rom("input"));
+        run_command(&mut cpu, "");
+        let room = walk_to(&mut cpu, &map, "Hull Breach", "Warp Drive Maintenance").unwrap();
+        assert_eq!(room.name, "Warp Drive Maintenance");
+        assert_eq!(room.items, vec!["space heater"]);
+        let room = walk_to(&mut cpu, &map, "Warp Drive Maintenance", "Hull Breach").unwrap();
+        assert_eq!(room.name, "Hull Breach");
+        assert_eq!(walk_to(&mut cpu, &map, "Hull Breach", "Hull Breach"), None);
+        assert_eq!(walk_to(&mut cpu, &map, "Hull Breach", "Kitchen"), None);
+    }
+}
@@ -0,0 +1,80 @@
+/// Grid position as (x, y), with y growing downwards like the puzzle maps
+pub type Position = (i64, i64);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Direction {
+    pub const ALL: [Direction; 4] = [
+        Direction::Up,
+        Direction::Down,
+        Direction::Left,
+        Direction::Right,
+    ];
+
+    pub fn turn_left(self) -> Direction {
+        match self {
+            Direction::Up => Direction::Left,
+            Direction::Left => Direction::Down,
+            Direction::Down => Direction::Right,
+            Direction::Right => Direction::Up,
+        }
+    }
+
+    pub fn turn_right(self) -> Direction {
+        match self {
+            Direction::Up => Direction::Right,
+            Direction::Right => Direction::Down,
+            Direction::Down => Direction::Left,
+            Direction::Left => Direction::Up,
+        }
+    }
+
+    /// The position one tile over from `pos` in this direction
+    pub fn step(self, pos: Position) -> Position {
+        match self {
+            Direction::Up => (pos.0, pos.1 - 1),
+            Direction::Down => (pos.0, pos.1 + 1),
+            Direction::Left => (pos.0 - 1, pos.1),
+            Direction::Right => (pos.0 + 1, pos.1),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn turns_go_round_both_ways() {
+        let clockwise = [
+            Direction::Up,
+            Direction::Right,
+            Direction::Down,
+            Direction::Left,
+        ];
+        for (i, direction) in clockwise.iter().enumerate() {
+            let next = clockwise[(i + 1) % 4];
+            assert_eq!(direction.turn_right(), next);
+            assert_eq!(next.turn_left(), *direction);
+        }
+    }
+
+    #[test]
+    fn steps_are_one_tile_with_y_down() {
+        let pos = (3, -2);
+        assert_eq!(Direction::Up.step(pos), (3, -3));
+        assert_eq!(Direction::Down.step(pos), (3, -1));
+        assert_eq!(Direction::Left.step(pos), (2, -2));
+        assert_eq!(Direction::Right.step(pos), (4, -2));
+        for direction in Direction::ALL.iter() {
+            let back = direction.turn_left().turn_left();
+            assert_eq!(back.step(direction.step(pos)), pos);
+        }
+    }
+}
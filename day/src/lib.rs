@@ -0,0 +1,32 @@
+use std::fs;
+
+pub mod direction;
+pub use direction::Direction;
+
+/// A single day's puzzle, with both parts solved from the raw contents of its input
+///
+/// Each part returns the answer exactly as it should be printed. Parsing the input can fail, so the parts return a
+/// `Result` instead of a bare `String`: tests can check that bad input is rejected, and `run` is the one place that
+/// gives up on it.
+pub trait Day {
+    type Error: std::fmt::Display;
+
+    fn part1(&self, input: &str) -> Result<String, Self::Error>;
+    fn part2(&self, input: &str) -> Result<String, Self::Error>;
+
+    /// Both answers at once, for days that can share the work between parts
+    fn solve(&self, input: &str) -> Result<(String, String), Self::Error> {
+        Ok((self.part1(input)?, self.part2(input)?))
+    }
+}
+
+/// Reads `filename` and prints both answers in the usual `N-1:` / `N-2:` format, panicking on invalid input like the
+/// days without a `Day` impl do
+pub fn run(number: usize, day: &impl Day, filename: &str) {
+    let input = fs::read_to_string(filename).unwrap();
+    let (part1, part2) = day
+        .solve(&input)
+        .unwrap_or_else(|e| panic!("Invalid input: {}", e));
+    println!("{}-1:\n{}", number, part1);
+    println!("{}-2:\n{}", number, part2);
+}
@@ -1,5 +1,7 @@
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 use std::fs;
+use std::io;
+use std::num::ParseIntError;
 use std::ops::{Index, IndexMut};
 
 #[derive(Debug)]
@@ -16,6 +18,23 @@ enum OpCode {
     Stop,
 }
 
+impl OpCode {
+    fn name(&self) -> &'static str {
+        match self {
+            OpCode::Add => "Add",
+            OpCode::Mul => "Mul",
+            OpCode::In => "In",
+            OpCode::Out => "Out",
+            OpCode::Jnz => "Jnz",
+            OpCode::Jz => "Jz",
+            OpCode::Lt => "Lt",
+            OpCode::Eq => "Eq",
+            OpCode::AddBp => "AddBp",
+            OpCode::Stop => "Stop",
+        }
+    }
+}
+
 #[derive(Debug)]
 enum OpParamMode {
     POINTER,
@@ -52,7 +71,32 @@ impl OpParam {
             OpParamMode::RELATIVE => {
                 memory[address + self.offset + Op::OPCODE_LENGTH] + base as i64
             }
-            OpParamMode::VALUE => panic!("Value parameters cannot be written to"),
+            OpParamMode::VALUE => unreachable!("Value parameters are rejected when decoding"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum DecodeError {
+    UnknownOp { address: usize, code: i64 },
+    UnknownParamMode { address: usize, mode: usize },
+    ImmediateWrite { address: usize },
+}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::UnknownOp { address, code } => {
+                write!(f, "Unknown op {} at address {}", code, address)
+            }
+            DecodeError::UnknownParamMode { address, mode } => {
+                write!(f, "Unknown parameter mode {} at address {}", mode, address)
+            }
+            DecodeError::ImmediateWrite { address } => write!(
+                f,
+                "Value parameter used as write target at address {}",
+                address
+            ),
         }
     }
 }
@@ -64,44 +108,56 @@ struct Op {
 }
 
 impl Op {
-    fn new(memory: &Memory, address: usize) -> Op {
-        let code = memory[address] % 100; // Last 2 digits
-        let parameter_modes = (memory[address] / 100)
-            .to_string()
-            .chars()
-            .map(|n| n.to_digit(10).unwrap() as usize)
-            .rev()
-            .collect::<Vec<_>>(); // Preceeding digits
-        fn parse_parameter(modes: &Vec<usize>, param_count: usize) -> Vec<OpParam> {
+    fn new(memory: &Memory, address: usize) -> Result<Op, DecodeError> {
+        let instruction = memory[address];
+        if instruction < 0 {
+            return Err(DecodeError::UnknownOp {
+                address,
+                code: instruction,
+            });
+        }
+        let code = instruction % 100; // Last 2 digits
+        fn parse_parameter(
+            instruction: i64,
+            param_count: usize,
+            write_param: Option<usize>,
+            address: usize,
+        ) -> Result<Vec<OpParam>, DecodeError> {
             let mut params: Vec<OpParam> = vec![];
             for i in 0..param_count {
-                let mode = match modes.get(i).unwrap_or(&0) {
+                // Preceeding digits, the first parameter's mode is the hundreds digit
+                let mode = (instruction / 10_i64.pow(i as u32 + 2) % 10) as usize;
+                let mode = match mode {
                     0 => OpParamMode::POINTER,
+                    1 if write_param == Some(i) => {
+                        return Err(DecodeError::ImmediateWrite { address })
+                    }
                     1 => OpParamMode::VALUE,
                     2 => OpParamMode::RELATIVE,
-                    _ => panic!("Unknown parameter mode"),
+                    mode => return Err(DecodeError::UnknownParamMode { address, mode }),
                 };
                 params.push(OpParam::new(mode, i));
             }
-            params
+            Ok(params)
         };
-        let (code, parameter_count) = match code {
-            1 => (OpCode::Add, 3),
-            2 => (OpCode::Mul, 3),
-            3 => (OpCode::In, 1),
-            4 => (OpCode::Out, 1),
-            5 => (OpCode::Jnz, 2),
-            6 => (OpCode::Jz, 2),
-            7 => (OpCode::Lt, 3),
-            8 => (OpCode::Eq, 3),
-            9 => (OpCode::AddBp, 1),
-            99 => (OpCode::Stop, 0),
-            _ => panic!("Unknown op: {}", code),
+        // Index of the parameter that gets written to, if any
+        let (code, parameter_count, write_param) = match code {
+            1 => (OpCode::Add, 3, Some(2)),
+            2 => (OpCode::Mul, 3, Some(2)),
+            3 => (OpCode::In, 1, Some(0)),
+            4 => (OpCode::Out, 1, None),
+            5 => (OpCode::Jnz, 2, None),
+            6 => (OpCode::Jz, 2, None),
+            7 => (OpCode::Lt, 3, Some(2)),
+            8 => (OpCode::Eq, 3, Some(2)),
+            9 => (OpCode::AddBp, 1, None),
+            99 => (OpCode::Stop, 0, None),
+            _ => return Err(DecodeError::UnknownOp { address, code }),
         };
-        Op {
+        Ok(Op {
             code,
-            params: parse_parameter(&parameter_modes, parameter_count),
-        }
+            params: parse_parameter(instruction, parameter_count, write_param, address)?,
+        })
     }
 
     fn len(&self) -> usize {
@@ -119,6 +175,39 @@ pub fn parse_rom(filename: &str) -> Vec<i64> {
         .collect()
 }
 
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    Parse(ParseIntError),
+}
+
+impl std::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadError::Io(err) => write!(f, "Could not read rom: {}", err),
+            LoadError::Parse(err) => write!(f, "Invalid value in rom: {}", err),
+        }
+    }
+}
+
+impl From<io::Error> for LoadError {
+    fn from(err: io::Error) -> LoadError {
+        LoadError::Io(err)
+    }
+}
+
+impl From<ParseIntError> for LoadError {
+    fn from(err: ParseIntError) -> LoadError {
+        LoadError::Parse(err)
+    }
+}
+
+fn parse_rom_str(src: &str) -> Result<Vec<i64>, LoadError> {
+    src.split(",")
+        .map(|op| op.trim().parse().map_err(LoadError::from))
+        .collect()
+}
+
 #[derive(Clone)]
 struct Memory {
     data: Vec<i64>,
@@ -137,6 +226,15 @@ impl Memory {
         memory.data = data;
         memory
     }
+    /// Memory contents with trailing uninitialized cells trimmed off, since they're indistinguishable from unwritten memory
+    fn initialized(&self) -> &[i64] {
+        let len = self
+            .data
+            .iter()
+            .rposition(|v| *v != Memory::UNINITIALIZED)
+            .map_or(0, |i| i + 1);
+        &self.data[..len]
+    }
     const UNINITIALIZED: i64 = 0;
 }
 
@@ -169,6 +267,8 @@ impl IndexMut<usize> for Memory {
 pub enum Poll {
     Result(i64),
     None,
+    NeedInput,
+    Fault(DecodeError),
     Stop,
 }
 
@@ -181,15 +281,65 @@ impl Poll {
     }
 }
 
+/// Output of an ASCII program, which usually ends with a single number too large to be a character
+#[derive(Debug, PartialEq)]
+pub enum AsciiOut {
+    Char(char),
+    Value(i64),
+}
+
+/// Runs `rom` with no input and checks it outputs an exact copy of itself, a good end to end check of relative mode
+/// and memory extension
+pub fn is_quine(rom: &[i64]) -> bool {
+    let mut cpu = Cpu::new(rom.to_vec());
+    let mut outputs = vec![];
+    for event in cpu.drive(&vec![]) {
+        match event {
+            Poll::Result(output) if outputs.len() < rom.len() => outputs.push(output),
+            Poll::Stop => return outputs == rom,
+            _ => return false,
+        }
+    }
+    false
+}
+
+#[derive(Debug)]
+pub enum IntcodeError {
+    Load(LoadError),
+    Decode(DecodeError),
+    MissingInput,
+    /// Too many outputs without halting, along with everything output up to the limit
+    OutputLimit(Vec<i64>),
+}
+
+impl From<LoadError> for IntcodeError {
+    fn from(err: LoadError) -> IntcodeError {
+        IntcodeError::Load(err)
+    }
+}
+
+impl From<DecodeError> for IntcodeError {
+    fn from(err: DecodeError) -> IntcodeError {
+        IntcodeError::Decode(err)
+    }
+}
+
+/// Loads a ROM from `src` and runs it to completion on `input`, returning every output
+pub fn run_all(src: &str, input: &[i64]) -> Result<Vec<i64>, IntcodeError> {
+    Cpu::from_rom_str(src)?.run_to_halt(&input.to_vec(), None)
+}
+
 #[derive(Clone)]
 pub struct Cpu {
-    pub inputs: VecDeque<i64>,
+    inputs: VecDeque<i64>,
     memory: Memory,
     ax: i64,
     bx: i64,
     cx: i64,
     sp: usize,
     bp: usize,
+    // Executions per opcode, only tracked while profiling
+    profile: Option<HashMap<&'static str, u64>>,
 }
 
 impl Cpu {
@@ -202,13 +352,83 @@ impl Cpu {
             cx: 0,
             bp: 0,
             sp: 0,
+            profile: None,
+        }
+    }
+
+    /// Creates a CPU with `inputs` already queued, ready to `run`
+    pub fn with_inputs(memory: Vec<i64>, inputs: &[i64]) -> Cpu {
+        let mut cpu = Cpu::new(memory);
+        cpu.inputs.extend(inputs);
+        cpu
+    }
+
+    /// Copies the program's state to branch from, without any of the inputs still queued up here
+    pub fn fork(&self) -> Cpu {
+        Cpu {
+            inputs: VecDeque::new(),
+            ..self.clone()
         }
     }
 
+    pub fn from_rom_file(path: &str) -> Result<Cpu, LoadError> {
+        Cpu::from_rom_str(&fs::read_to_string(path)?)
+    }
+
+    pub fn from_rom_str(src: &str) -> Result<Cpu, LoadError> {
+        Ok(Cpu::new(parse_rom_str(src)?))
+    }
+
     pub fn push(&mut self, input: &Vec<i64>) {
         self.inputs.extend(input.iter().cloned());
     }
 
+    pub fn clear_inputs(&mut self) {
+        self.inputs.clear();
+    }
+
+    pub fn pending_inputs(&self) -> &VecDeque<i64> {
+        &self.inputs
+    }
+
+    pub fn input_len(&self) -> usize {
+        self.inputs.len()
+    }
+
+    pub fn has_pending_input(&self) -> bool {
+        !self.inputs.is_empty()
+    }
+
+    /// True when the next op is an `In` with nothing queued for it, so polling would just return `Poll::NeedInput`
+    pub fn is_waiting_for_input(&self) -> bool {
+        match Op::new(&self.memory, self.sp) {
+            Ok(op) => matches!(op.code, OpCode::In) && self.inputs.is_empty(),
+            Err(_) => false,
+        }
+    }
+
+    /// FNV-1a over the little endian bytes of initialized memory, so digests stay the same across Rust releases
+    pub fn memory_digest(&self) -> u64 {
+        const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+        const FNV_PRIME: u64 = 0x0100_0000_01b3;
+        self.memory
+            .initialized()
+            .iter()
+            .flat_map(|word| word.to_le_bytes().to_vec())
+            .fold(FNV_OFFSET_BASIS, |hash, byte| {
+                (hash ^ byte as u64).wrapping_mul(FNV_PRIME)
+            })
+    }
+
+    /// Compares memory against `other`, treating cells missing from either side as uninitialized
+    pub fn memory_eq(&self, other: &[i64]) -> bool {
+        let memory = self.memory.initialized();
+        (0..std::cmp::max(memory.len(), other.len())).all(|i| {
+            memory.get(i).unwrap_or(&Memory::UNINITIALIZED)
+                == other.get(i).unwrap_or(&Memory::UNINITIALIZED)
+        })
+    }
+
     fn read_param(&self, param: &OpParam) -> i64 {
         param.read(&self.memory, self.bp, self.sp)
     }
@@ -218,8 +438,44 @@ impl Cpu {
     }
 
     pub fn poll(&mut self) -> Poll {
-        let op = Op::new(&self.memory, self.sp);
-        self.run_op(&op)
+        match Op::new(&self.memory, self.sp) {
+            Ok(op) => {
+                let event = self.run_op(&op);
+                // An op waiting on input hasn't run yet, it'll be counted once it does
+                if let (Some(profile), false) =
+                    (&mut self.profile, matches!(event, Poll::NeedInput))
+                {
+                    *profile.entry(op.code.name()).or_insert(0) += 1;
+                }
+                event
+            }
+            Err(err) => Poll::Fault(err),
+        }
+    }
+
+    /// Starts or stops counting how many times each opcode runs. Stopping discards the counts so far.
+    pub fn set_profiling(&mut self, enabled: bool) {
+        self.profile = match (enabled, self.profile.take()) {
+            (true, Some(profile)) => Some(profile),
+            (true, None) => Some(HashMap::new()),
+            (false, _) => None,
+        };
+    }
+
+    /// Executions per opcode since profiling was enabled
+    pub fn profile(&self) -> HashMap<&'static str, u64> {
+        self.profile.clone().unwrap_or_default()
+    }
+
+    /// Executes up to `max_ops` instructions, stopping early on output, halt or missing input.
+    pub fn run_burst(&mut self, max_ops: u64) -> Poll {
+        for _ in 0..max_ops {
+            match self.poll() {
+                Poll::None => (),
+                event => return event,
+            }
+        }
+        Poll::None
     }
 
     pub fn run(&mut self) -> Option<i64> {
@@ -230,16 +486,114 @@ impl Cpu {
         self.push(input);
 
         loop {
-            let op = Op::new(&self.memory, self.sp);
-            match self.run_op(&op) {
+            match self.poll() {
                 Poll::Result(output) => return Some(output),
                 Poll::Stop => break,
+                Poll::NeedInput => panic!("Missing input parameter"),
+                Poll::Fault(err) => panic!("{}", err),
                 Poll::None => (),
             }
         }
         None
     }
 
+    /// Runs until the next output, returning `Poll::Result` with it, or whichever event (halt, missing input or fault)
+    /// stopped execution first. The CPU stays suspended after an output and can be resumed by calling this again.
+    pub fn next_output(&mut self, input: &Vec<i64>) -> Poll {
+        self.push(input);
+
+        loop {
+            match self.poll() {
+                Poll::None => (),
+                event => return event,
+            }
+        }
+    }
+
+    /// Runs until the program halts, returning every output. Stops early with `IntcodeError::OutputLimit` once more
+    /// than `max_outputs` are produced, so a program that never halts can't buffer forever.
+    pub fn run_to_halt(
+        &mut self,
+        input: &Vec<i64>,
+        max_outputs: Option<usize>,
+    ) -> Result<Vec<i64>, IntcodeError> {
+        let mut outputs = vec![];
+        for event in self.drive(input) {
+            match event {
+                Poll::Result(_) if Some(outputs.len()) == max_outputs => {
+                    return Err(IntcodeError::OutputLimit(outputs))
+                }
+                Poll::Result(output) => outputs.push(output),
+                Poll::NeedInput => return Err(IntcodeError::MissingInput),
+                Poll::Fault(err) => return Err(err.into()),
+                Poll::Stop | Poll::None => (),
+            }
+        }
+        Ok(outputs)
+    }
+
+    /// Collects up to `n` outputs, fewer only if the program halts first
+    pub fn run_n_outputs(&mut self, n: usize, input: &Vec<i64>) -> Vec<i64> {
+        let mut outputs = vec![];
+        self.push(input);
+        while outputs.len() < n {
+            match self.run() {
+                Some(output) => outputs.push(output),
+                None => break,
+            }
+        }
+        outputs
+    }
+
+    /// Collects outputs until `stop` returns true for one (which is included) or the program halts
+    pub fn run_collect_until(
+        &mut self,
+        input: &Vec<i64>,
+        mut stop: impl FnMut(i64) -> bool,
+    ) -> Vec<i64> {
+        let mut outputs = vec![];
+        self.push(input);
+        while let Some(output) = self.run() {
+            outputs.push(output);
+            if stop(output) {
+                break;
+            }
+        }
+        outputs
+    }
+
+    /// Yields every output, followed by whichever event (halt, missing input or fault) stopped execution
+    pub fn drive<'a>(&'a mut self, input: &Vec<i64>) -> impl Iterator<Item = Poll> + 'a {
+        self.push(input);
+        let mut finished = false;
+        std::iter::from_fn(move || {
+            if finished {
+                return None;
+            }
+            loop {
+                match self.poll() {
+                    Poll::None => (),
+                    Poll::Result(output) => return Some(Poll::Result(output)),
+                    event => {
+                        finished = true;
+                        return Some(event);
+                    }
+                }
+            }
+        })
+    }
+
+    pub fn ascii_outputs<'a>(
+        &'a mut self,
+        input: &Vec<i64>,
+    ) -> impl Iterator<Item = AsciiOut> + 'a {
+        self.drive(input).filter_map(|event| match event {
+            Poll::Result(output @ 0..=255) => Some(AsciiOut::Char(output as u8 as char)),
+            Poll::Result(output) => Some(AsciiOut::Value(output)),
+            _ => None,
+        })
+    }
+
     fn run_op(&mut self, op: &Op) -> Poll {
         match op.code {
             OpCode::Add => {
@@ -261,8 +615,11 @@ impl Cpu {
             OpCode::In => {
                 self.ax = self.read_param_as_ptr(&op.params[0]);
 
-                self.memory[self.ax as usize] =
-                    self.inputs.pop_front().expect("Missing input parameter");
+                // Leave the stack pointer on this op so it can be resumed once input arrives
+                match self.inputs.pop_front() {
+                    Some(input) => self.memory[self.ax as usize] = input,
+                    None => return Poll::NeedInput,
+                }
                 self.sp += op.len();
             }
             OpCode::Out => {
@@ -316,3 +673,323 @@ impl Cpu {
         Poll::None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_rom_file_reports_missing_file() {
+        assert!(matches!(
+            Cpu::from_rom_file("does/not/exist"),
+            Err(LoadError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn from_rom_str_reports_malformed_rom() {
+        assert!(matches!(
+            Cpu::from_rom_str("1,2,x,4"),
+            Err(LoadError::Parse(_))
+        ));
+        let mut cpu = Cpu::from_rom_str("104,7,99\n").unwrap();
+        assert_eq!(cpu.run(), Some(7));
+    }
+
+    #[test]
+    fn run_burst_stops_at_first_event() {
+        let rom = vec![1, 0, 0, 0, 104, 5, 104, 6, 99];
+        let (mut polled, mut burst) = (Cpu::new(rom.to_vec()), Cpu::new(rom.to_vec()));
+        assert!(matches!(polled.poll(), Poll::None));
+        assert!(matches!(burst.run_burst(1), Poll::None));
+        assert!(polled.memory_eq(burst.memory.initialized()));
+
+        assert!(matches!(burst.run_burst(100), Poll::Result(5)));
+        assert!(matches!(burst.run_burst(100), Poll::Result(6)));
+        assert!(matches!(burst.run_burst(100), Poll::Stop));
+    }
+
+    #[test]
+    fn memory_digest_is_stable() {
+        let mut cpu = Cpu::new(vec![1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50]);
+        assert_eq!(cpu.run(), None);
+        assert!(cpu.memory_eq(&[3500, 9, 10, 70, 2, 3, 11, 0, 99, 30, 40, 50]));
+        assert!(cpu.memory_eq(&[3500, 9, 10, 70, 2, 3, 11, 0, 99, 30, 40, 50, 0, 0]));
+        assert!(!cpu.memory_eq(&[3500, 9, 10, 70]));
+        assert_eq!(cpu.memory_digest(), 0x0078_a4e5_fff9_d34a);
+    }
+
+    #[test]
+    fn clear_inputs_leaves_in_waiting() {
+        let mut cpu = Cpu::with_inputs(vec![3, 0, 4, 0, 99], &[1, 2]);
+        assert_eq!(
+            cpu.pending_inputs().iter().cloned().collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+        cpu.clear_inputs();
+        assert!(cpu.pending_inputs().is_empty());
+        assert!(matches!(cpu.poll(), Poll::NeedInput));
+        cpu.push(&vec![3]);
+        assert_eq!(cpu.run(), Some(3));
+    }
+
+    #[test]
+    fn malformed_instructions_are_decode_errors() {
+        assert!(matches!(
+            run_all("-101,0,0,0,99", &[]),
+            Err(IntcodeError::Decode(DecodeError::UnknownOp {
+                address: 0,
+                code: -101
+            }))
+        ));
+        assert!(matches!(
+            run_all("1,0,0,0,42", &[]),
+            Err(IntcodeError::Decode(DecodeError::UnknownOp {
+                address: 4,
+                code: 42
+            }))
+        ));
+        assert!(matches!(
+            run_all("1,0,0,0,11101,1,2,3,99", &[]),
+            Err(IntcodeError::Decode(DecodeError::ImmediateWrite {
+                address: 4
+            }))
+        ));
+        assert!(matches!(
+            run_all("304,0,99", &[]),
+            Err(IntcodeError::Decode(DecodeError::UnknownParamMode {
+                address: 0,
+                mode: 3
+            }))
+        ));
+        assert_eq!(run_all("1101,2,3,5,104,0,99", &[]).unwrap(), vec![5]);
+    }
+
+    #[test]
+    fn run_collect_until_stops_at_first_negative() {
+        let mut cpu = Cpu::new(vec![104, 5, 104, -3, 104, 7, 99]);
+        assert_eq!(cpu.run_collect_until(&vec![], |out| out < 0), vec![5, -3]);
+        assert_eq!(cpu.run_collect_until(&vec![], |out| out < 0), vec![7]);
+        assert!(cpu.run_collect_until(&vec![], |_| true).is_empty());
+    }
+
+    #[test]
+    fn drive_ends_with_the_terminating_poll() {
+        let mut cpu = Cpu::new(vec![104, 1, 104, 2, 99]);
+        let events: Vec<Poll> = cpu.drive(&vec![]).collect();
+        assert_eq!(events.len(), 3);
+        assert_eq!(
+            events
+                .iter()
+                .filter_map(|e| e.to_option())
+                .collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+        assert!(matches!(events.last(), Some(Poll::Stop)));
+
+        let mut cpu = Cpu::new(vec![3, 9, 4, 9, 3, 9, 4, 9, 99, 0]);
+        let events: Vec<Poll> = cpu.drive(&vec![7]).collect();
+        assert!(matches!(events[..], [Poll::Result(7), Poll::NeedInput]));
+        let events: Vec<Poll> = cpu.drive(&vec![8]).collect();
+        assert!(matches!(events[..], [Poll::Result(8), Poll::Stop]));
+    }
+
+    #[test]
+    fn recognises_quine() {
+        let quine = vec![
+            109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+        ];
+        assert!(is_quine(&quine));
+        assert!(!is_quine(&[104, 1, 99]));
+        assert!(!is_quine(&[3, 0, 99]));
+    }
+
+    #[test]
+    fn ascii_outputs_on_day_17() {
+        let mut rom = parse_rom("../17/input");
+        rom[0] = 2;
+        let routine = "A,B,A,B,C,A,C,A,C,B\n\
+                       R,12,L,8,L,4,L,4\n\
+                       L,8,R,6,L,6\n\
+                       L,8,L,4,R,12,L,6,L,4\n\
+                       n\n";
+        let input = routine.bytes().map(|b| b as i64).collect::<Vec<i64>>();
+        let mut cpu = Cpu::new(rom);
+        let outputs: Vec<AsciiOut> = cpu.ascii_outputs(&input).collect();
+        let (last, view) = outputs.split_last().unwrap();
+        assert_eq!(*last, AsciiOut::Value(761085));
+        assert!(view.iter().all(|out| matches!(out, AsciiOut::Char(_))));
+        let text: String = view
+            .iter()
+            .filter_map(|out| match out {
+                AsciiOut::Char(c) => Some(*c),
+                AsciiOut::Value(_) => None,
+            })
+            .collect();
+        assert!(text.contains("Main:\n"));
+    }
+
+    #[test]
+    fn run_all_day_5_comparisons() {
+        let equal_8 = "3,9,8,9,10,9,4,9,99,-1,8";
+        let less_than_8 = "3,9,7,9,10,9,4,9,99,-1,8";
+        let equal_8_immediate = "3,3,1108,-1,8,3,4,3,99";
+        let less_than_8_immediate = "3,3,1107,-1,8,3,4,3,99";
+        for (input, equal, less) in [(7, 0, 1), (8, 1, 0), (9, 0, 0)].iter() {
+            for (src, expected) in [
+                (equal_8, equal),
+                (less_than_8, less),
+                (equal_8_immediate, equal),
+                (less_than_8_immediate, less),
+            ]
+            .iter()
+            {
+                assert_eq!(
+                    run_all(src, &[*input]).unwrap(),
+                    vec![**expected],
+                    "{} on {}",
+                    src,
+                    input
+                );
+            }
+        }
+        let around_8 =
+            "3,21,1008,21,8,20,1005,20,22,107,8,21,20,1006,20,31,1106,0,36,98,0,0,1002,21,125,20,\
+                        4,20,1105,1,46,104,999,1105,1,46,1101,1000,1,20,4,20,1105,1,46,98,99";
+        assert_eq!(run_all(around_8, &[5]).unwrap(), vec![999]);
+        assert_eq!(run_all(around_8, &[8]).unwrap(), vec![1000]);
+        assert_eq!(run_all(around_8, &[11]).unwrap(), vec![1001]);
+        assert!(matches!(
+            run_all(equal_8, &[]),
+            Err(IntcodeError::MissingInput)
+        ));
+        assert!(matches!(run_all("3,x", &[]), Err(IntcodeError::Load(_))));
+    }
+
+    #[test]
+    fn run_to_halt_caps_endless_output() {
+        // Counts up from 0 forever
+        let mut cpu = Cpu::new(vec![4, 100, 1001, 100, 1, 100, 1105, 1, 0]);
+        assert!(matches!(
+            cpu.run_to_halt(&vec![], Some(5)),
+            Err(IntcodeError::OutputLimit(ref outputs)) if outputs[..] == [0, 1, 2, 3, 4]
+        ));
+        let mut cpu = Cpu::new(vec![104, 1, 104, 2, 99]);
+        assert_eq!(cpu.run_to_halt(&vec![], Some(2)).unwrap(), vec![1, 2]);
+        let mut cpu = Cpu::new(vec![104, 1, 104, 2, 99]);
+        assert!(matches!(
+            cpu.run_to_halt(&vec![], Some(1)),
+            Err(IntcodeError::OutputLimit(ref outputs)) if outputs[..] == [1]
+        ));
+    }
+
+    #[test]
+    fn input_accessors_follow_the_queue() {
+        let mut cpu = Cpu::new(vec![3, 0, 3, 0, 99]);
+        assert_eq!(cpu.input_len(), 0);
+        assert!(!cpu.has_pending_input());
+        cpu.push(&vec![1, 2, 3]);
+        assert_eq!(cpu.input_len(), 3);
+        assert!(cpu.has_pending_input());
+        // Both reads consume an input, then the program halts with one left over
+        assert_eq!(cpu.run(), None);
+        assert_eq!(cpu.input_len(), 1);
+        assert!(cpu.has_pending_input());
+        cpu.clear_inputs();
+        assert_eq!(cpu.input_len(), 0);
+        assert!(!cpu.has_pending_input());
+    }
+
+    #[test]
+    fn with_inputs_queues_before_running() {
+        // Echoes three inputs back
+        let echo = vec![3, 0, 4, 0, 3, 0, 4, 0, 3, 0, 4, 0, 99];
+        let mut cpu = Cpu::with_inputs(echo.clone(), &[5, -6, 7]);
+        assert_eq!(cpu.input_len(), 3);
+        assert_eq!(cpu.run(), Some(5));
+        assert_eq!(cpu.run(), Some(-6));
+        assert_eq!(cpu.run(), Some(7));
+        assert_eq!(cpu.run(), None);
+        assert!(Cpu::with_inputs(echo, &[]).pending_inputs().is_empty());
+    }
+
+    #[test]
+    fn fork_keeps_state_but_drops_inputs() {
+        // Moves the relative base to 20, then echoes inputs through [20] forever
+        let rom = vec![109, 20, 203, 0, 204, 0, 1105, 1, 2];
+        let mut cpu = Cpu::with_inputs(rom.clone(), &[5, 6]);
+        assert_eq!(cpu.run(), Some(5));
+        let mut fork = cpu.fork();
+        assert_eq!(fork.memory_digest(), cpu.memory_digest());
+        assert!(fork.pending_inputs().is_empty());
+        assert_eq!(cpu.input_len(), 1);
+        // Picks up at the same op with the same relative base
+        assert_eq!(fork.run_with(&vec![9]), Some(9));
+        let mut expected = rom;
+        expected.resize(21, 0);
+        expected[20] = 9;
+        assert!(fork.memory_eq(&expected));
+        assert_eq!(cpu.run(), Some(6));
+    }
+
+    #[test]
+    fn profile_counts_each_opcode() {
+        let mut cpu = Cpu::new(vec![1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50]);
+        assert!(cpu.profile().is_empty());
+        cpu.set_profiling(true);
+        assert_eq!(cpu.run(), None);
+        let profile = cpu.profile();
+        assert_eq!(profile.len(), 3);
+        assert_eq!(profile["Add"], 1);
+        assert_eq!(profile["Mul"], 1);
+        assert_eq!(profile["Stop"], 1);
+
+        // Reads twice and echoes, the read left waiting on input isn't counted until it runs
+        let mut cpu = Cpu::new(vec![3, 7, 4, 7, 1105, 1, 0, 0]);
+        cpu.set_profiling(true);
+        assert_eq!(cpu.run_with(&vec![7]), Some(7));
+        assert!(matches!(cpu.next_output(&vec![]), Poll::NeedInput));
+        assert_eq!(cpu.profile()["In"], 1);
+        assert_eq!(cpu.profile()["Jnz"], 1);
+        cpu.set_profiling(true);
+        assert_eq!(cpu.run_with(&vec![8]), Some(8));
+        assert_eq!(cpu.profile()["In"], 2);
+        assert_eq!(cpu.profile()["Out"], 2);
+        cpu.set_profiling(false);
+        assert!(cpu.profile().is_empty());
+    }
+
+    #[test]
+    fn waiting_for_input_until_pushed() {
+        // Doubles one input then halts
+        let mut cpu = Cpu::new(vec![3, 9, 1002, 9, 2, 9, 4, 9, 99, 0]);
+        assert!(cpu.is_waiting_for_input());
+        assert!(matches!(cpu.poll(), Poll::NeedInput));
+        assert!(cpu.is_waiting_for_input());
+        cpu.push(&vec![21]);
+        assert!(!cpu.is_waiting_for_input());
+        assert_eq!(cpu.run(), Some(42));
+        assert!(!cpu.is_waiting_for_input());
+        assert_eq!(cpu.run(), None);
+        assert!(!cpu.is_waiting_for_input());
+        // Not an op at all
+        assert!(!Cpu::new(vec![42]).is_waiting_for_input());
+    }
+
+    #[test]
+    fn run_n_outputs_resumes_where_it_stopped() {
+        let rom = parse_rom("../13/input");
+        let screen = Cpu::new(rom.to_vec()).run_to_halt(&vec![], None).unwrap();
+        assert_eq!(screen.len() % 3, 0);
+        let mut cpu = Cpu::new(rom);
+        let first = cpu.run_n_outputs(3, &vec![]);
+        assert_eq!(first, screen[..3]);
+        // The screen starts drawing from the top left wall tile
+        assert_eq!(first, vec![0, 0, 1]);
+        assert_eq!(cpu.run_n_outputs(3, &vec![]), screen[3..6]);
+        // Asking for more than is left gives a short read once it halts
+        let rest = cpu.run_n_outputs(screen.len(), &vec![]);
+        assert_eq!(rest, screen[6..]);
+        assert!(cpu.run_n_outputs(3, &vec![]).is_empty());
+    }
+}